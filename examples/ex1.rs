@@ -1,172 +1,143 @@
 extern crate rtlsdr;
 
-use std::os::raw::{c_void, c_uchar};
-use std::ptr;
-use rtlsdr::Error;
+use rtlsdr::{DeviceConfig, Gain, Hertz, Ppm, Result, SampleRate};
 use std::time::Duration;
 use std::thread;
 
-#[allow(unused_mut)]
-fn sdr_config(dev: &rtlsdr::Device) -> Error {
-    let (m, p, s, mut err) = dev.get_usb_strings();
-    match err {
-        Error::NoError => println!("set_xtal_freq successful"),
-        _ => return err,
-    };
-    println!("m: {}\n p: {}\n s: {}\n err: {:?}\n", m, p, s, err);
+fn sdr_config(dev: &rtlsdr::Device) -> Result<()> {
+    let (m, p, s) = dev.get_usb_strings()?;
+    println!("get_usb_strings successful");
+    println!("m: {}\n p: {}\n s: {}\n", m, p, s);
 
     // ---------- Get/Set/Get Hardware Info ----------
     println!("1. Getting hardware info...");
-    let (mut hw_info, mut err) = dev.get_hw_info();
-
-    println!("Error: {:?}", err);
-    println!("Vendor ID:             {:?}", hw_info.vendor_id);
-    println!("Product ID:            {:?}", hw_info.product_id);
-    println!("Manufacturer:          {:?}", hw_info.manufact);
-    println!("Product:               {:?}", hw_info.product);
-    println!("Serial number:         {:?}", hw_info.serial);
-    println!("Serial number enabled: {:?}", hw_info.have_serial);
-    println!("IR endpoint enabled:   {:?}", hw_info.enable_ir);
-    println!("Remote wakeup enabled: {:?}", hw_info.remote_wakeup);
-    println!("");
-
-    println!("Writing hardware info...");
-    err = dev.set_hw_info(&hw_info);
-    println!("Writing hardware info return message: {:?}\n", err);
+    match dev.get_hw_info() {
+        Ok(hw_info) => {
+            print_hw_info(&hw_info);
+
+            println!("Writing hardware info...");
+            match dev.set_hw_info(&hw_info) {
+                Ok(()) => println!("Writing hardware info successful\n"),
+                Err(e) => println!("Writing hardware info failed - {}\n", e),
+            }
+        }
+        Err(e) => println!("Error: {}\n", e),
+    }
 
     println!("2. Getting hardware info...");
-    let (hw_info, mut err) = dev.get_hw_info();
-
-    println!("Error: {:?}", err);
-    println!("Vendor ID:             {:?}", hw_info.vendor_id);
-    println!("Product ID:            {:?}", hw_info.product_id);
-    println!("Manufacturer:          {:?}", hw_info.manufact);
-    println!("Product:               {:?}", hw_info.product);
-    println!("Serial number:         {:?}", hw_info.serial);
-    println!("Serial number enabled: {:?}", hw_info.have_serial);
-    println!("IR endpoint enabled:   {:?}", hw_info.enable_ir);
-    println!("Remote wakeup enabled: {:?}", hw_info.remote_wakeup);
-    println!("");
+    match dev.get_hw_info() {
+        Ok(hw_info) => print_hw_info(&hw_info),
+        Err(e) => println!("Error: {}\n", e),
+    }
 
     // ---------- Get Tuner Gain ----------
     println!("get_tuner_type: {}", dev.get_tuner_type());
-    err = dev.set_xtal_freq(28800000, 28800000);
-    match err {
-        Error::NoError => println!("set_xtal_freq - 28800000"),
-        _ => return err,
-    };
-    println!("");
+    dev.set_xtal_freq(Hertz::new(28800000), Hertz::new(28800000))?;
+    println!("set_xtal_freq - 28800000");
+    println!();
 
     // ---------- Set Tuner Gain ----------
-    err = dev.set_tuner_gain_mode(true);
-    match err {
-        Error::NoError => println!("set_tuner_gain_mode successful..."),
-        _ => return err,
-    };
-
-    let (gains, mut err) = dev.get_tuner_gains();
-    match err {
-        Error::NoError => println!("get_tuner_gains successful..."),
-        _ => println!("get_tuner_gains failed - {:?}", err), // return err,
+    let gains = match dev.get_tuner_gains() {
+        Ok(gains) => {
+            println!("get_tuner_gains successful...");
+            gains
+        }
+        Err(e) => {
+            println!("get_tuner_gains failed - {}", e);
+            return Err(e);
+        }
     };
 
     println!("\ntuner gains:  {:?}\n", gains);
 
-    err = dev.set_tuner_gain(gains[2]);
-    match err {
-        Error::NoError => println!("set_tuner_gain {:?} successful...", gains[2]),
-        _ => return err,
-    };
-    println!("");
+    let gain = dev.set_gain_db(20.0)?;
+    println!("set_gain_db 20 dB successful, using {}...", gain);
+    println!("get_gain: {:?}", dev.get_gain());
+    println!();
 
     // ---------- Get/Set Sample Rate ----------
-    let samplerate: i32 = 2083334;
-    err = dev.set_sample_rate(samplerate);
-    match err {
-        Error::NoError => println!("set_sample_rate {} successful...", samplerate),
-        _ => return err,
-    };
+    let samplerate: SampleRate = "2.083334Msps".parse()?;
+    dev.set_sample_rate(samplerate)?;
+    println!("set_sample_rate {} successful...", samplerate);
 
     println!("get_sample_rate {} successful...\n", dev.get_sample_rate());
 
     // ---------- Get/Set Xtal Freq ----------
-    let (mut rtl_freq, mut tuner_freq, mut err) = dev.get_xtal_freq();
-    match err {
-        Error::NoError => {
-            println!("get_xtal_freq successful - rtl_freq: {}, tuner_freq: {}",
-                     rtl_freq,
-                     tuner_freq)
-        }
-        _ => return err,
-    };
+    let (mut rtl_freq, mut tuner_freq) = dev.get_xtal_freq()?;
+    println!("get_xtal_freq successful - rtl_freq: {}, tuner_freq: {}",
+             rtl_freq,
+             tuner_freq);
 
-    rtl_freq = 28800000;
-    tuner_freq = 28800000;
+    rtl_freq = Hertz::new(28800000);
+    tuner_freq = Hertz::new(28800000);
 
-    err = dev.set_xtal_freq(rtl_freq, tuner_freq);
-    match err {
-        Error::NoError => {
-            println!("set_xtal_freq successful - rtl_freq: {}, tuner_freq: {}",
-                     rtl_freq,
-                     tuner_freq)
-        }
-        _ => return err,
-    };
-    println!("");
+    dev.set_xtal_freq(rtl_freq, tuner_freq)?;
+    println!("set_xtal_freq successful - rtl_freq: {}, tuner_freq: {}",
+             rtl_freq,
+             tuner_freq);
+    println!();
 
     // ---------- Get/Set Center Freq ----------
-    err = dev.set_center_freq(978000000);
-    match err {
-        Error::NoError => println!("set_center_freq successful - 978000000"),
-        _ => return err,
-    };
+    dev.set_center_freq("978M".parse()?)?;
+    println!("set_center_freq successful - 978000000");
 
     println!("get_center_freq: {}\n", dev.get_center_freq());
 
     // ---------- Set Tuner Bandwidth ----------
-    let bw: i32 = 1000000;
+    let bw = Hertz::new(1000000);
     println!("Setting bandwidth: {}", bw);
 
-    err = dev.set_tuner_bandwidth(bw);
-    match err {
-        Error::NoError => println!("set_tuner_bandwidth {} Successful", bw),
-        _ => return err,
-    };
-    println!("");
+    dev.set_tuner_bandwidth(bw)?;
+    println!("set_tuner_bandwidth {} Successful", bw);
+    println!();
 
     // ---------- Buffer Reset ----------
-    err = dev.reset_buffer();
-    match err {
-        Error::NoError => println!("reset_buffer successful..."),
-        _ => return err,
-    };
+    dev.reset_buffer()?;
+    println!("reset_buffer successful...");
 
     // ---------- Get/Set Freq Correction ----------
     let mut freq_corr = dev.get_freq_correction();
     println!("get_freq_correction - {}", freq_corr);
 
-    freq_corr += 1;
-    let err = dev.set_freq_correction(freq_corr);
-    match err {
-        Error::NoError => println!("set_freq_correction successful - {}", freq_corr),
-        _ => return err,
-    };
-    println!("");
+    freq_corr = Ppm::new(freq_corr.ppm() + 1);
+    dev.set_freq_correction(freq_corr)?;
+    println!("set_freq_correction successful - {}", freq_corr);
+    println!();
+
+    // ---------- Apply a DeviceConfig ----------
+    let config = DeviceConfig::new()
+        .center_freq("1090M".parse()?)
+        .sample_rate("2.4Msps".parse()?)
+        .gain(Gain::Max)
+        .agc(false);
+    let readback = dev.apply(&config)?;
+    println!("apply successful - {:?}", readback);
+    println!();
     // ----------  ----------
-    Error::NoError
+    Ok(())
+}
+
+fn print_hw_info(hw_info: &rtlsdr::HwInfo) {
+    println!("Vendor ID:             {:?}", hw_info.vendor_id);
+    println!("Product ID:            {:?}", hw_info.product_id);
+    println!("Manufacturer:          {:?}", hw_info.manufact);
+    println!("Product:               {:?}", hw_info.product);
+    println!("Serial number:         {:?}", hw_info.serial);
+    println!("Serial number enabled: {:?}", hw_info.have_serial);
+    println!("IR endpoint enabled:   {:?}", hw_info.enable_ir);
+    println!("Remote wakeup enabled: {:?}", hw_info.remote_wakeup);
+    println!();
 }
 
-unsafe extern "C" fn read_async_callback(buf: *mut c_uchar, len: u32, ctx: *mut c_void) {
-    let _ = ctx;
-    let v = Vec::<u8>::from_raw_parts(buf, len as usize, len as usize);
-    println!("----- read_async_callback buffer size - {}", len);
+fn read_async_callback(buf: &[u8]) {
+    println!("----- read_async_callback buffer size - {}", buf.len());
     println!("----- {} {} {} {} {} {}",
-             v[0],
-             v[1],
-             v[2],
-             v[3],
-             v[4],
-             v[5]);
+             buf[0],
+             buf[1],
+             buf[2],
+             buf[3],
+             buf[4],
+             buf[5]);
 }
 
 
@@ -178,9 +149,23 @@ fn main() {
         return;
     }
 
-    for i in 0..count {
-        let (m, p, s, err) = rtlsdr::get_device_usb_strings(i);
-        println!("get_device_usb_strings: {:?} - {} {} {}", err, m, p, s);
+    match rtlsdr::probe_devices() {
+        Ok(infos) => {
+            for info in infos {
+                let ids = info.usb_ids
+                    .map_or_else(|| "unknown IDs".to_string(),
+                                 |(vid, pid)| format!("{:04x}:{:04x}", vid, pid));
+                println!("{}: {} ({}) {} {} {}, tuner {:?}",
+                         info.index,
+                         info.name,
+                         ids,
+                         info.manufacturer,
+                         info.product,
+                         info.serial,
+                         info.tuner);
+            }
+        }
+        Err(e) => println!("probe_devices: {}", e),
     }
 
     let index = 0;
@@ -189,53 +174,73 @@ fn main() {
              rtlsdr::get_device_name(0));
     println!("===== Running tests using device indx: 0 =====\n");
 
-    let (dev, mut err) = rtlsdr::open(index);
-    match err {
-        Error::NoError => println!("open successful"),
-        _ => return,
-    }
+    let dev = match rtlsdr::open(index) {
+        Ok(dev) => {
+            println!("open successful");
+            dev
+        }
+        Err(e) => {
+            println!("open failed - {}", e);
+            return;
+        }
+    };
 
-    err = sdr_config(&dev);
-    match err {
-        Error::NoError => println!("sdr_config successful..."),
-        _ => return,
+    match sdr_config(&dev) {
+        Ok(()) => println!("sdr_config successful..."),
+        Err(e) => {
+            println!("sdr_config failed - {}", e);
+            return;
+        }
     }
 
     println!("calling read_sync...");
     for i in 0..10 {
-        let (_, read_count, err) = dev.read_sync(rtlsdr::DEFAULT_BUF_LENGTH);
         println!("----- read_sync requested iteration {} -----", i);
         println!("\tread_sync requested - {}", rtlsdr::DEFAULT_BUF_LENGTH);
-        println!("\tread_sync received  - {}", read_count);
-        println!("\tread_sync err msg   - {:?}", err);
+        match dev.read_sync(rtlsdr::DEFAULT_BUF_LENGTH) {
+            Ok(buf) => println!("\tread_sync received  - {}", buf.len()),
+            Err(e) => println!("\tread_sync err msg   - {}", e),
+        }
     }
 
-    dev.reset_buffer();
+    if let Err(e) = dev.reset_buffer() {
+        println!("reset_buffer failed - {}", e);
+    }
 
     // read_async is a blocking call and doesn't return until
     // async_stop is explicitly called, so we spawn a thread
     // that sleeps for a bit while our async callback runs...
-    let d = dev.clone();
+    let canceller = dev.canceller();
     thread::spawn(move || {
         println!("async_stop thread sleeping for 5 seconds...");
         thread::sleep(Duration::from_millis(5000));
         println!("async_stop thread awake, canceling read async...");
-        d.cancel_async();
+        if let Err(e) = canceller.cancel_async() {
+            println!("cancel_async failed - {}", e);
+        }
     });
 
     println!("calling read_async...");
-    err = dev.read_async(Some(read_async_callback),
-                         ptr::null_mut(),
-                         rtlsdr::DEFAULT_ASYNC_BUF_NUMBER,
-                         rtlsdr::DEFAULT_BUF_LENGTH);
-    match err {
-        Error::NoError => println!("read_async returned successfully..."),
-        _ => println!("read_async return error - {:?}", err),
+    match dev.read_async(rtlsdr::DEFAULT_ASYNC_BUF_NUMBER,
+                         rtlsdr::DEFAULT_BUF_LENGTH,
+                         read_async_callback) {
+        Ok(()) => println!("read_async returned successfully..."),
+        Err(e) => println!("read_async return error - {}", e),
+    }
+
+    println!("calling stream...");
+    let mut stream = dev.stream(rtlsdr::StreamOptions::default());
+    for buf in stream.by_ref().take(10) {
+        match buf {
+            Ok(buf) => println!("----- stream buffer {} size - {}", buf.seq(), buf.len()),
+            Err(e) => println!("stream error - {}", e),
+        }
     }
+    println!("stream dropped {} buffers", stream.dropped());
+    let dev = stream.stop();
 
-    err = dev.close();
-    match err {
-        Error::NoError => println!("device close successful..."),
-        _ => println!("dev close error - {:?}", err),
+    match dev.close() {
+        Ok(()) => println!("device close successful..."),
+        Err(e) => println!("dev close error - {}", e),
     }
 }
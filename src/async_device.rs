@@ -0,0 +1,298 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+
+use futures::channel::oneshot;
+
+use crate::stream::Queue;
+use crate::{AsyncControl, Buffer, Canceller, Device, Error, ErrorKind, Gain, Hertz, Ppm, Result,
+            SampleRate, SamplingMode, SdrSource, StreamOptions, TenthDb};
+
+type Call<S> = Box<dyn FnOnce(&S) + Send>;
+
+enum Command<S> {
+    Call(Call<S>),
+    Stream(Arc<Queue>, StreamOptions),
+}
+
+/// A device driven from async code.
+///
+/// The device lives on a worker thread. Control calls are queued to it and
+/// run between sample buffers while a stream is active, so they never race
+/// the read, also while the worker waits for a consumer that's busy
+/// awaiting one of them. The futures don't depend on a particular executor.
+///
+/// Any `SdrSource` can be driven this way, e.g. a simulated device in tests.
+pub struct AsyncDevice<S: SdrSource + Send + 'static = Device> {
+    tx: Option<Sender<Command<S>>>,
+    canceller: Canceller,
+    thread: Option<JoinHandle<S>>,
+    // The queue of the stream the worker is reading, if any.
+    active: Arc<Mutex<Option<Arc<Queue>>>>,
+}
+
+/// Sample buffers from an `AsyncDevice`, as a `futures::Stream`.
+///
+/// Dropping it cancels the read, the device stays usable.
+pub struct SampleStream {
+    queue: Arc<Queue>,
+    canceller: Canceller,
+}
+
+fn gone() -> Error {
+    Error::with_kind("AsyncDevice", ErrorKind::NoDevice, 0)
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Runs the commands queued during a stream, `Stop` once the AsyncDevice is
+// gone.
+fn run_queued<S: SdrSource>(dev: &S, rx: &Receiver<Command<S>>) -> AsyncControl {
+    loop {
+        match rx.try_recv() {
+            Ok(Command::Call(f)) => f(dev),
+            Ok(Command::Stream(other, _)) => {
+                other.finish(Err(Error::with_kind("AsyncDevice::stream", ErrorKind::Busy, 0)))
+            }
+            Err(TryRecvError::Empty) => return AsyncControl::Continue,
+            Err(TryRecvError::Disconnected) => return AsyncControl::Stop,
+        }
+    }
+}
+
+fn run<S: SdrSource>(dev: &S, rx: &Receiver<Command<S>>, active: &Mutex<Option<Arc<Queue>>>) {
+    while let Ok(cmd) = rx.recv() {
+        match cmd {
+            Command::Call(f) => f(dev),
+            Command::Stream(queue, opts) => {
+                *lock(active) = Some(queue.clone());
+                let res = dev.reset_buffer().and_then(|_| {
+                    dev.read_async_until(opts.buf_num, opts.buf_len, &mut |buf| {
+                        if run_queued(dev, rx) == AsyncControl::Stop {
+                            return AsyncControl::Stop;
+                        }
+                        queue.push_or_idle(buf, &mut || run_queued(dev, rx))
+                    })
+                });
+                *lock(active) = None;
+                queue.finish(res);
+            }
+        }
+    }
+}
+
+impl AsyncDevice {
+    /// Opens a device by index.
+    pub fn open(index: i32) -> Result<AsyncDevice> {
+        crate::open(index).map(AsyncDevice::new)
+    }
+}
+
+impl<S: SdrSource + Send + 'static> AsyncDevice<S> {
+    /// Moves the device to a worker thread.
+    pub fn new(dev: S) -> AsyncDevice<S> {
+        let (tx, rx) = mpsc::channel();
+        let canceller = dev.canceller();
+        let active = Arc::new(Mutex::new(None));
+        let worker_active = active.clone();
+        let thread = thread::spawn(move || {
+            run(&dev, &rx, &worker_active);
+            dev
+        });
+        AsyncDevice {
+            tx: Some(tx),
+            canceller,
+            thread: Some(thread),
+            active,
+        }
+    }
+
+    /// Stops the worker thread and returns the device.
+    pub fn into_device(mut self) -> S {
+        self.shutdown().unwrap()
+    }
+
+    fn shutdown(&mut self) -> Option<S> {
+        let thread = self.thread.take()?;
+        self.tx.take();
+        // A worker waiting for room in a full queue doesn't see the cancel.
+        if let Some(ref queue) = *lock(&self.active) {
+            queue.close();
+        }
+        let _ = self.canceller.cancel_async();
+        thread.join().ok()
+    }
+
+    async fn call<T, F>(&self, f: F) -> Result<T>
+        where F: FnOnce(&S) -> Result<T> + Send + 'static,
+              T: Send + 'static
+    {
+        let (tx, rx) = oneshot::channel();
+        let call: Call<S> = Box::new(move |dev| {
+            let _ = tx.send(f(dev));
+        });
+        match self.tx {
+            Some(ref cmds) if cmds.send(Command::Call(call)).is_ok() => {}
+            _ => return Err(gone()),
+        }
+        // The consumer may be awaiting this instead of draining the stream.
+        if let Some(ref queue) = *lock(&self.active) {
+            queue.wake();
+        }
+        rx.await.unwrap_or_else(|_| Err(gone()))
+    }
+
+    /// Starts streaming samples, only one stream can be active at a time.
+    pub fn stream(&self, opts: StreamOptions) -> SampleStream {
+        let queue = Arc::new(Queue::new(&opts));
+        let sent = match self.tx {
+            Some(ref cmds) => cmds.send(Command::Stream(queue.clone(), opts)).is_ok(),
+            None => false,
+        };
+        if !sent {
+            queue.finish(Err(gone()));
+        }
+        SampleStream {
+            queue,
+            canceller: self.canceller.clone(),
+        }
+    }
+
+    /// Sets the center frequency.
+    pub async fn set_center_freq(&self, freq: Hertz) -> Result<()> {
+        self.call(move |dev| dev.set_center_freq(freq)).await
+    }
+
+    /// Returns the tuned frequency or zero on error.
+    pub async fn get_center_freq(&self) -> Result<Hertz> {
+        self.call(|dev| Ok(dev.get_center_freq())).await
+    }
+
+    /// Sets the frequency correction.
+    pub async fn set_freq_correction(&self, ppm: Ppm) -> Result<()> {
+        self.call(move |dev| dev.set_freq_correction(ppm)).await
+    }
+
+    /// Returns the frequency correction value.
+    pub async fn get_freq_correction(&self) -> Result<Ppm> {
+        self.call(|dev| Ok(dev.get_freq_correction())).await
+    }
+
+    /// Returns a list of supported tuner gains.
+    pub async fn get_tuner_gains(&self) -> Result<Vec<TenthDb>> {
+        self.call(|dev| dev.get_tuner_gains()).await
+    }
+
+    /// Sets the tuner gain, manual gain mode must be enabled.
+    pub async fn set_tuner_gain(&self, gain: TenthDb) -> Result<()> {
+        self.call(move |dev| dev.set_tuner_gain(gain)).await
+    }
+
+    /// Returns the tuner gain.
+    pub async fn get_tuner_gain(&self) -> Result<TenthDb> {
+        self.call(|dev| Ok(dev.get_tuner_gain())).await
+    }
+
+    /// Sets the gain mode, automatic or manual.
+    pub async fn set_tuner_gain_mode(&self, manual_mode: bool) -> Result<()> {
+        self.call(move |dev| dev.set_tuner_gain_mode(manual_mode)).await
+    }
+
+    /// Sets the gain, see `SdrSource::set_gain`.
+    pub async fn set_gain(&self, gain: Gain) -> Result<Gain> {
+        self.call(move |dev| dev.set_gain(gain)).await
+    }
+
+    /// Returns the gain in use.
+    pub async fn get_gain(&self) -> Result<Gain> {
+        self.call(|dev| Ok(dev.get_gain())).await
+    }
+
+    /// Sets the intermediate frequency gain.
+    pub async fn set_tuner_if_gain(&self, stage: i32, gain: TenthDb) -> Result<()> {
+        self.call(move |dev| dev.set_tuner_if_gain(stage, gain)).await
+    }
+
+    /// Sets the device bandwidth.
+    pub async fn set_tuner_bandwidth(&self, bw: Hertz) -> Result<()> {
+        self.call(move |dev| dev.set_tuner_bandwidth(bw)).await
+    }
+
+    /// Sets the sample rate.
+    pub async fn set_sample_rate(&self, rate: SampleRate) -> Result<()> {
+        self.call(move |dev| dev.set_sample_rate(rate)).await
+    }
+
+    /// Returns the sample rate.
+    pub async fn get_sample_rate(&self) -> Result<SampleRate> {
+        self.call(|dev| Ok(dev.get_sample_rate())).await
+    }
+
+    /// Sets the AGC mode.
+    pub async fn set_agc_mode(&self, agc_mode: bool) -> Result<()> {
+        self.call(move |dev| dev.set_agc_mode(agc_mode)).await
+    }
+
+    /// Sets the direct sampling mode.
+    pub async fn set_direct_sampling(&self, mode: SamplingMode) -> Result<()> {
+        self.call(move |dev| dev.set_direct_sampling(mode)).await
+    }
+
+    /// Sets the offset tuning mode.
+    pub async fn set_offset_tuning(&self, enable: bool) -> Result<()> {
+        self.call(move |dev| dev.set_offset_tuning(enable)).await
+    }
+
+    /// Switches the bias tee on GPIO 0 on or off.
+    pub async fn set_bias_tee(&self, on: bool) -> Result<()> {
+        self.call(move |dev| dev.set_bias_tee(on)).await
+    }
+
+    /// Returns whether the bias tee on GPIO 0 is on.
+    pub async fn get_bias_tee(&self) -> Result<bool> {
+        self.call(|dev| Ok(dev.get_bias_tee())).await
+    }
+}
+
+impl<S: SdrSource + Send + 'static> Drop for AsyncDevice<S> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl SampleStream {
+    /// Returns the number of buffers discarded by the overflow policy.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped()
+    }
+}
+
+impl futures::Stream for SampleStream {
+    type Item = Result<Buffer>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Buffer>>> {
+        self.queue.poll_recv(cx)
+    }
+}
+
+impl Drop for SampleStream {
+    fn drop(&mut self) {
+        self.queue.close();
+        if !self.queue.is_finished() {
+            let _ = self.canceller.cancel_async();
+        }
+    }
+}
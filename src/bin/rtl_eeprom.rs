@@ -0,0 +1,210 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An EEPROM tool, command line compatible with the C rtl_eeprom where the
+//! options overlap.
+
+extern crate rtlsdr;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use rtlsdr::eeprom::{self, EepromImage, Preset};
+use rtlsdr::Result;
+
+#[derive(Default)]
+struct Args {
+    device: i32,
+    manufacturer: Option<String>,
+    product: Option<String>,
+    serial: Option<String>,
+    enable_ir: Option<bool>,
+    dump: Option<String>,
+    restore: Option<String>,
+    compare: Option<String>,
+    preset: Option<&'static Preset>,
+    dry_run: bool,
+    force: bool,
+    clear_trailer: bool,
+    yes: bool,
+}
+
+fn usage() -> ! {
+    let presets: Vec<String> =
+        eeprom::PRESETS.iter().map(|p| format!("\t\t{}: {}", p.name, p.description)).collect();
+    eprintln!("rtl_eeprom, an EEPROM programming tool for RTL2832 based DVB-T receivers
+
+Usage:
+\t[-d device_index (default: 0)]
+\t[-m <str> set manufacturer string]
+\t[-p <str> set product string]
+\t[-s <str> set serial number string]
+\t[-i <0,1> disable/enable IR-endpoint]
+\t[-g <conf> generate a preset config, keeping the serial number, one of:
+{}]
+\t[-w <filename> write dumped file to device]
+\t[-r <filename> dump EEPROM to file]
+\t[-c <filename> compare the EEPROM with a dumped file]
+\t[-n dry run, show the bytes that would be written]
+\t[-f force writing images without a valid header]
+\t[-y write without asking]
+\t[-h display this help text]
+
+Use on your own risk, especially -w!",
+              presets.join("\n"));
+    process::exit(1)
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    match value.map(|v| v.parse()) {
+        Some(Ok(v)) => v,
+        _ => {
+            eprintln!("invalid value for {}", flag);
+            usage()
+        }
+    }
+}
+
+fn parse_args() -> Args {
+    let mut args = Args::default();
+    let mut it = env::args().skip(1);
+    while let Some(flag) = it.next() {
+        match flag.as_str() {
+            "-d" => args.device = parse(&flag, it.next()),
+            "-m" => args.manufacturer = Some(parse(&flag, it.next())),
+            "-p" => args.product = Some(parse(&flag, it.next())),
+            "-s" => args.serial = Some(parse(&flag, it.next())),
+            "-i" => args.enable_ir = Some(parse::<u8>(&flag, it.next()) != 0),
+            "-w" => args.restore = Some(parse(&flag, it.next())),
+            "-r" => args.dump = Some(parse(&flag, it.next())),
+            "-c" => args.compare = Some(parse(&flag, it.next())),
+            "-g" => {
+                let name: String = parse(&flag, it.next());
+                args.preset = Some(eeprom::preset(&name).unwrap_or_else(|| usage()));
+            }
+            "-n" => args.dry_run = true,
+            "-f" => args.force = true,
+            "-t" => args.clear_trailer = true,
+            "-y" => args.yes = true,
+            _ => usage(),
+        }
+    }
+    args
+}
+
+fn print_image(image: &EepromImage) {
+    let none = || "none".to_string();
+    println!("__________________________________________");
+    println!("Vendor ID:\t\t0x{:04x}", image.vendor_id);
+    println!("Product ID:\t\t0x{:04x}", image.product_id);
+    println!("Manufacturer:\t\t{}", image.manufacturer().unwrap_or_else(none));
+    println!("Product:\t\t{}", image.product().unwrap_or_else(none));
+    println!("Serial number:\t\t{}", image.serial().unwrap_or_else(none));
+    println!("Serial number enabled:\t{}", if image.have_serial() { "yes" } else { "no" });
+    println!("IR endpoint enabled:\t{}", if image.enable_ir() { "yes" } else { "no" });
+    println!("Remote wakeup enabled:\t{}", if image.remote_wakeup() { "yes" } else { "no" });
+    if !image.has_valid_header() {
+        println!("No valid EEPROM header found.");
+    }
+    println!("__________________________________________");
+}
+
+fn confirm(args: &Args) -> bool {
+    if args.yes {
+        return true;
+    }
+    print!("Write new configuration to device [y/n]? ");
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    let _ = io::stdin().lock().read_line(&mut answer);
+    answer.trim().eq_ignore_ascii_case("y")
+}
+
+fn run(args: &Args) -> Result<()> {
+    let dev = rtlsdr::open(args.device)?;
+    println!("Using device {}: {}", args.device, rtlsdr::get_device_name(args.device));
+
+    let current = match args.dump {
+        Some(ref path) => {
+            let image = eeprom::dump(&dev, path)?;
+            println!("Dump of the EEPROM written to {}.", path);
+            image
+        }
+        None => dev.read_eeprom_image()?,
+    };
+    print_image(&current);
+
+    if let Some(ref path) = args.compare {
+        let diffs = eeprom::diff(&current, &eeprom::load(path)?);
+        if diffs.is_empty() {
+            println!("The EEPROM matches {}.", path);
+        }
+        for d in diffs {
+            println!("{}", d);
+        }
+    }
+
+    let mut new = match (args.restore.as_ref(), args.preset) {
+        (Some(path), _) => eeprom::load(path)?,
+        (None, Some(preset)) => eeprom::render_preset(&dev, preset)?,
+        (None, None) => current.clone(),
+    };
+    if let Some(ref s) = args.manufacturer {
+        new.set_manufacturer(s)?;
+    }
+    if let Some(ref s) = args.product {
+        new.set_product(s)?;
+    }
+    if let Some(ref s) = args.serial {
+        new.set_serial(s)?;
+        new.set_have_serial(true);
+    }
+    if let Some(on) = args.enable_ir {
+        new.set_enable_ir(on);
+    }
+    if args.clear_trailer {
+        new.clear_trailer();
+    }
+
+    let diffs = eeprom::diff(&current, &new);
+    if diffs.is_empty() {
+        return Ok(());
+    }
+    println!("\nNew configuration:");
+    print_image(&new);
+    for d in &diffs {
+        println!("{}", d);
+    }
+    if args.dry_run {
+        print!("\n{}", new.hexdump()?);
+        println!("Dry run, nothing written.");
+        return Ok(());
+    }
+    if !confirm(args) {
+        println!("Nothing written.");
+        return Ok(());
+    }
+    eeprom::write_verified(&dev, &new, args.force)?;
+    println!("Configuration successfully written and verified.");
+    println!("Please replug the device for changes to take effect.");
+    Ok(())
+}
+
+fn main() {
+    let args = parse_args();
+    if rtlsdr::get_device_count() == 0 {
+        eprintln!("No supported devices found.");
+        process::exit(1);
+    }
+    if let Err(e) = run(&args) {
+        eprintln!("rtl_eeprom failed - {}", e);
+        process::exit(1);
+    }
+}
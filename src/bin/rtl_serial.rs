@@ -0,0 +1,139 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Gives the attached dongles unique serial numbers, see `rtlsdr::serial`.
+
+extern crate rtlsdr;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use rtlsdr::serial::{self, Scheme};
+use rtlsdr::Result;
+
+struct Args {
+    scheme: Scheme,
+    backup_dir: PathBuf,
+    write: bool,
+    clear_trailer: bool,
+}
+
+fn usage() -> ! {
+    eprintln!("rtl_serial, assigns unique serial numbers to RTL2832 based DVB-T receivers
+
+Usage:\trtl_serial [-options]
+\t[-s number sequential serials from (default: 1)]
+\t[-r random serials]
+\t[-m index=serial, set a dongle's serial, may be repeated]
+\t[-b directory to back the EEPROMs up to (default: .)]
+\t[-w write the serials, otherwise only show what would change]");
+    process::exit(1)
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        scheme: Scheme::Sequential { start: 1 },
+        backup_dir: PathBuf::from("."),
+        write: false,
+        clear_trailer: false,
+    };
+    let mut map = BTreeMap::new();
+
+    let mut it = env::args().skip(1);
+    while let Some(flag) = it.next() {
+        match flag.as_str() {
+            "-s" => {
+                match it.next().map(|v| v.parse()) {
+                    Some(Ok(start)) => args.scheme = Scheme::Sequential { start },
+                    _ => usage(),
+                }
+            }
+            "-r" => args.scheme = Scheme::Random,
+            "-m" => {
+                let value = it.next().unwrap_or_else(|| usage());
+                let mut parts = value.splitn(2, '=');
+                match (parts.next().map(|i| i.parse()), parts.next()) {
+                    (Some(Ok(index)), Some(serial)) => {
+                        map.insert(index, serial.to_string());
+                    }
+                    _ => usage(),
+                }
+            }
+            "-b" => args.backup_dir = PathBuf::from(it.next().unwrap_or_else(|| usage())),
+            "-w" => args.write = true,
+            "-t" => args.clear_trailer = true,
+            _ => usage(),
+        }
+    }
+    if !map.is_empty() {
+        args.scheme = Scheme::Mapped(map);
+    }
+    args
+}
+
+fn run(args: &Args) -> Result<()> {
+    let infos = rtlsdr::devices()?;
+    for info in &infos {
+        println!("{}: {}, {}, {}, SN: {}",
+                 info.index,
+                 info.name,
+                 info.manufacturer,
+                 info.product,
+                 info.serial);
+    }
+    let serials: Vec<String> = infos.iter().map(|i| i.serial.clone()).collect();
+    for dup in serial::duplicates(&serials) {
+        println!("Serial {} is shared by more than one dongle.", dup);
+    }
+
+    let changes = serial::plan(&serials, &args.scheme)?;
+    if changes.is_empty() {
+        println!("Nothing to change.");
+        return Ok(());
+    }
+    for c in &changes {
+        println!("{}: {} -> {}", c.index, c.old, c.new);
+    }
+    if !args.write {
+        println!("Run again with -w to write the serials.");
+        return Ok(());
+    }
+
+    // Only the dongles being changed, the others may be in use.
+    let opened =
+        changes.iter().map(|c| rtlsdr::open(c.index as i32)).collect::<Result<Vec<_>>>()?;
+    let dongles: Vec<_> = changes.iter().map(|c| c.index).zip(&opened).collect();
+    let reports = serial::assign(&dongles, &changes, &args.backup_dir, args.clear_trailer)?;
+    for r in &reports {
+        println!("{}: wrote {}, backup in {}",
+                 r.change.index,
+                 r.change.new,
+                 r.backup.display());
+    }
+    let replug: Vec<String> =
+        reports.iter().filter(|r| r.needs_replug).map(|r| r.change.index.to_string()).collect();
+    if !replug.is_empty() {
+        println!("Replug dongle(s) {} for the new serials to take effect.", replug.join(", "));
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = parse_args();
+    if rtlsdr::get_device_count() == 0 {
+        eprintln!("No supported devices found.");
+        process::exit(1);
+    }
+    if let Err(e) = run(&args) {
+        eprintln!("rtl_serial failed - {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! An rtl_tcp server, command line compatible with the C rtl_tcp where the
+//! options overlap.
+
+extern crate rtlsdr;
+
+use std::env;
+use std::process;
+
+use rtlsdr::tcp::{Server, ServerOptions};
+use rtlsdr::{ErrorKind, Gain, Hertz, Overflow, Ppm, Result, SampleRate, TenthDb};
+
+struct Args {
+    addr: String,
+    port: u16,
+    freq: Hertz,
+    gain: Option<TenthDb>,
+    rate: SampleRate,
+    ppm: Ppm,
+    device: String,
+    opts: ServerOptions,
+}
+
+fn usage() -> ! {
+    eprintln!("rtl_tcp, an I/Q spectrum server for RTL2832 based DVB-T receivers
+
+Usage:\trtl_tcp [-options]
+\t[-a listen address (default: 127.0.0.1)]
+\t[-p listen port (default: 1234)]
+\t[-f frequency to tune to, e.g. 978M (default: 100M)]
+\t[-g gain in dB (default: 0 for auto)]
+\t[-s samplerate, e.g. 2.048Msps (default: 2.048Msps)]
+\t[-b number of buffers (default: 15)]
+\t[-n max number of queued buffers (default: 500)]
+\t[-d device serial or index (default: 0)]
+\t[-P ppm_error (default: 0)]
+\t[-O slow client policy: block, drop-oldest, drop-newest (default: drop-oldest)]
+\t[-m disconnect after this many dropped buffers (default: never)]");
+    process::exit(1)
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    match value.map(|v| v.parse()) {
+        Some(Ok(v)) => v,
+        _ => {
+            eprintln!("invalid value for {}", flag);
+            usage()
+        }
+    }
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        addr: "127.0.0.1".to_string(),
+        port: 1234,
+        freq: Hertz::new(100_000_000),
+        gain: None,
+        rate: SampleRate::new(2_048_000),
+        ppm: Ppm::new(0),
+        device: "0".to_string(),
+        opts: ServerOptions::default(),
+    };
+    args.opts.stream.buf_num = 15;
+    args.opts.stream.capacity = 500;
+    args.opts.stream.overflow = Overflow::DropOldest;
+
+    let mut it = env::args().skip(1);
+    while let Some(flag) = it.next() {
+        let value = it.next();
+        match flag.as_str() {
+            "-a" => args.addr = parse(&flag, value),
+            "-p" => args.port = parse(&flag, value),
+            "-f" => args.freq = parse(&flag, value),
+            "-g" => {
+                let gain: TenthDb = parse(&flag, value);
+                args.gain = if gain.tenths() == 0 { None } else { Some(gain) };
+            }
+            "-s" => args.rate = parse(&flag, value),
+            "-b" => args.opts.stream.buf_num = parse(&flag, value),
+            "-n" => args.opts.stream.capacity = parse(&flag, value),
+            "-d" => args.device = parse(&flag, value),
+            "-P" => args.ppm = parse(&flag, value),
+            "-O" => {
+                args.opts.stream.overflow = match value.as_deref() {
+                    Some("block") => Overflow::Block,
+                    Some("drop-oldest") => Overflow::DropOldest,
+                    Some("drop-newest") => Overflow::DropNewest,
+                    _ => usage(),
+                }
+            }
+            "-m" => args.opts.max_dropped = Some(parse(&flag, value)),
+            _ => usage(),
+        }
+    }
+    args
+}
+
+fn open(args: &Args) -> Result<rtlsdr::Device> {
+    // Serials are usually numeric too, e.g. "00000978", so a serial match
+    // wins over an index.
+    let dev = match (rtlsdr::open_by_serial(&args.device), args.device.parse()) {
+        (Ok(dev), _) => {
+            println!("Using device with serial {}.", args.device);
+            dev
+        }
+        (Err(ref e), Ok(index)) if e.kind() == ErrorKind::NotFound => {
+            println!("Using device {}: {}", index, rtlsdr::get_device_name(index));
+            rtlsdr::open(index)?
+        }
+        (Err(e), _) => return Err(e),
+    };
+
+    if args.ppm.ppm() != 0 {
+        dev.set_freq_correction(args.ppm)?;
+    }
+    dev.set_sample_rate(args.rate)?;
+    dev.set_center_freq(args.freq)?;
+    println!("Tuned to {}.", args.freq);
+    match dev.set_gain(args.gain.map_or(Gain::Auto, Gain::Manual))? {
+        Gain::Manual(gain) => println!("Tuner gain set to {}.", gain),
+        _ => println!("Tuner gain set to automatic."),
+    }
+    Ok(dev)
+}
+
+fn main() {
+    let args = parse_args();
+    if rtlsdr::get_device_count() == 0 {
+        eprintln!("No supported devices found.");
+        process::exit(1);
+    }
+    let dev = match open(&args) {
+        Ok(dev) => dev,
+        Err(e) => {
+            eprintln!("Failed to open rtlsdr device - {}", e);
+            process::exit(1);
+        }
+    };
+
+    let server = match Server::bind((args.addr.as_str(), args.port), dev, args.opts) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Failed to listen on {}:{} - {}", args.addr, args.port, e);
+            process::exit(1);
+        }
+    };
+    println!("listening...");
+    println!("Use the device argument 'rtl_tcp={}:{}' in OsmoSDR (gr-osmosdr) source",
+             args.addr,
+             args.port);
+    if let Err(e) = server.run() {
+        eprintln!("rtl_tcp stopped - {}", e);
+        process::exit(1);
+    }
+}
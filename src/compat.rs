@@ -0,0 +1,335 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Deprecated `(value, Error)` tuple API.
+//!
+//! Mirrors the pre-`Result` signatures so existing code can switch its
+//! imports to `rtlsdr::compat` and migrate call by call, reaching the new
+//! API through `Device::with_device`.
+#![allow(deprecated)]
+
+use std::os::raw::c_void;
+use std::sync::{Arc, Mutex};
+
+use super::{Canceller, Hertz, HwInfo, Ppm, ReadAsyncCbT, SampleRate, SamplingMode, TenthDb};
+
+/// Tuple API error codes, `NoError` signals success.
+#[deprecated(note = "use rtlsdr::Error and rtlsdr::Result")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    NoError,
+    Io,
+    InvalidParam,
+    Access,
+    NoDevice,
+    NotFound,
+    Busy,
+    Timeout,
+    Overflow,
+    Pipe,
+    Interrupted,
+    NoMem,
+    NotSupported,
+    NoValidEEPROMHeader,
+    StringValueTooLong,
+    StringDescriptorInvalid,
+    StringDescriptorTooLong,
+    Unknown,
+}
+
+impl<'a> From<&'a super::Error> for Error {
+    fn from(e: &'a super::Error) -> Error {
+        use super::ErrorKind;
+        match e.kind() {
+            ErrorKind::Io => Error::Io,
+            ErrorKind::InvalidParam => Error::InvalidParam,
+            ErrorKind::Access => Error::Access,
+            ErrorKind::NoDevice => Error::NoDevice,
+            ErrorKind::NotFound => Error::NotFound,
+            ErrorKind::Busy => Error::Busy,
+            ErrorKind::Timeout => Error::Timeout,
+            ErrorKind::Overflow => Error::Overflow,
+            ErrorKind::Pipe => Error::Pipe,
+            ErrorKind::Interrupted => Error::Interrupted,
+            ErrorKind::NoMem => Error::NoMem,
+            ErrorKind::NotSupported => Error::NotSupported,
+            ErrorKind::NoValidEEPROMHeader => Error::NoValidEEPROMHeader,
+            ErrorKind::StringValueTooLong => Error::StringValueTooLong,
+            ErrorKind::StringDescriptorInvalid => Error::StringDescriptorInvalid,
+            ErrorKind::StringDescriptorTooLong => Error::StringDescriptorTooLong,
+            ErrorKind::TrailerNotErased | ErrorKind::Ambiguous | ErrorKind::Unknown => {
+                Error::Unknown
+            }
+        }
+    }
+}
+
+fn to_err<T>(r: &super::Result<T>) -> Error {
+    match *r {
+        Ok(_) => Error::NoError,
+        Err(ref e) => Error::from(e),
+    }
+}
+
+fn split<T: Default>(r: super::Result<T>) -> (T, Error) {
+    let err = to_err(&r);
+    (r.unwrap_or_default(), err)
+}
+
+/// Device handle with the tuple API.
+///
+/// Calls are serialised on an internal lock, except `read_async` which runs
+/// outside it so other calls, `cancel_async` included, can be made while it
+/// blocks. Closing during a read cancels it, the device closes once the read
+/// returns. A failed `open` yields a handle whose calls return `NoDevice`.
+#[deprecated(note = "use rtlsdr::Device")]
+pub struct Device {
+    dev: Mutex<Option<Arc<Shared>>>,
+    canceller: Option<Canceller>,
+}
+
+// The device shared with a running read_async. librtlsdr takes control calls
+// while rtlsdr_read_async blocks, and the lock keeps those to one at a time.
+struct Shared(super::Device);
+
+unsafe impl Sync for Shared {}
+
+/// Returns the information of a device by index.
+#[deprecated(note = "use rtlsdr::get_device_usb_strings")]
+pub fn get_device_usb_strings(index: i32) -> (String, String, String, Error) {
+    let ((m, p, s), err) = split(super::get_device_usb_strings(index));
+    (m, p, s, err)
+}
+
+/// Returns a device index by serial id.
+#[deprecated(note = "use rtlsdr::get_index_by_serial")]
+pub fn get_index_by_serial(serial: String) -> i32 {
+    match super::get_index_by_serial(serial) {
+        Ok(i) => i,
+        Err(e) => e.code(),
+    }
+}
+
+/// Returns an opened device by index.
+#[deprecated(note = "use rtlsdr::open")]
+pub fn open(index: i32) -> (Arc<Device>, Error) {
+    match super::open(index) {
+        Ok(dev) => {
+            let canceller = Some(dev.canceller());
+            (Arc::new(Device {
+                 dev: Mutex::new(Some(Arc::new(Shared(dev)))),
+                 canceller,
+             }),
+             Error::NoError)
+        }
+        Err(ref e) => {
+            (Arc::new(Device {
+                 dev: Mutex::new(None),
+                 canceller: None,
+             }),
+             Error::from(e))
+        }
+    }
+}
+
+impl Device {
+    /// Runs `f` against the underlying `Result` API device, or returns `None`
+    /// once the device is closed.
+    pub fn with_device<T, F>(&self, f: F) -> Option<T>
+        where F: FnOnce(&super::Device) -> T
+    {
+        let dev = self.dev.lock().unwrap_or_else(|e| e.into_inner());
+        dev.as_ref().map(|d| f(&d.0))
+    }
+
+    fn call<T, F>(&self, f: F) -> super::Result<T>
+        where F: FnOnce(&super::Device) -> super::Result<T>
+    {
+        self.with_device(f)
+            .unwrap_or_else(|| Err(super::Error::new("rtlsdr_open", -4)))
+    }
+
+    pub fn close(&self) -> Error {
+        let dev = self.dev.lock().unwrap_or_else(|e| e.into_inner()).take();
+        match dev.map(Arc::try_unwrap) {
+            Some(Ok(dev)) => to_err(&dev.0.close()),
+            // read_async still holds the device and drops it on return.
+            Some(Err(_)) => self.cancel_async(),
+            None => Error::NoDevice,
+        }
+    }
+
+    pub fn set_xtal_freq(&self, rtl_freq_hz: i32, tuner_freq_hz: i32) -> Error {
+        let rtl_freq = Hertz::new(rtl_freq_hz as u32);
+        let tuner_freq = Hertz::new(tuner_freq_hz as u32);
+        to_err(&self.call(|d| d.set_xtal_freq(rtl_freq, tuner_freq)))
+    }
+
+    pub fn get_xtal_freq(&self) -> (i32, i32, Error) {
+        let ((rtl, tuner), err) = split(self.call(|d| d.get_xtal_freq()));
+        (rtl.hz() as i32, tuner.hz() as i32, err)
+    }
+
+    pub fn get_usb_strings(&self) -> (String, String, String, Error) {
+        let ((m, p, s), err) = split(self.call(|d| d.get_usb_strings()));
+        (m, p, s, err)
+    }
+
+    pub fn write_eeprom(&self, data: Vec<u8>, offset: u8) -> Error {
+        to_err(&self.call(|d| d.write_eeprom(data, offset)))
+    }
+
+    pub fn read_eeprom(&self, offset: u8, len: u16) -> (Vec<u8>, Error) {
+        match self.call(|d| d.read_eeprom(offset, len)) {
+            Ok(v) => (v, Error::NoError),
+            Err(ref e) => (vec![0u8; len as usize], Error::from(e)),
+        }
+    }
+
+    pub fn set_center_freq(&self, freq_hz: i32) -> Error {
+        to_err(&self.call(|d| d.set_center_freq(Hertz::new(freq_hz as u32))))
+    }
+
+    pub fn get_center_freq(&self) -> i32 {
+        self.with_device(|d| d.get_center_freq().hz() as i32).unwrap_or(0)
+    }
+
+    pub fn set_freq_correction(&self, ppm: i32) -> Error {
+        to_err(&self.call(|d| d.set_freq_correction(Ppm::new(ppm))))
+    }
+
+    pub fn get_freq_correction(&self) -> i32 {
+        self.with_device(|d| d.get_freq_correction().ppm()).unwrap_or(0)
+    }
+
+    pub fn get_tuner_type(&self) -> String {
+        self.with_device(|d| d.get_tuner_type().to_string())
+            .unwrap_or_else(|| String::from("Unknown"))
+    }
+
+    pub fn get_tuner_gains(&self) -> (Vec<i32>, Error) {
+        split(self.call(|d| d.get_tuner_gains().map(|g| g.iter().map(|g| g.tenths()).collect())))
+    }
+
+    pub fn set_tuner_gain(&self, gain: i32) -> Error {
+        to_err(&self.call(|d| d.set_tuner_gain(TenthDb::new(gain))))
+    }
+
+    pub fn get_tuner_gain(&self) -> i32 {
+        self.with_device(|d| d.get_tuner_gain().tenths()).unwrap_or(0)
+    }
+
+    pub fn set_tuner_bandwidth(&self, bw_hz: i32) -> Error {
+        to_err(&self.call(|d| d.set_tuner_bandwidth(Hertz::new(bw_hz as u32))))
+    }
+
+    pub fn set_tuner_if_gain(&self, stage: i32, gains_tenths_db: i32) -> Error {
+        to_err(&self.call(|d| d.set_tuner_if_gain(stage, TenthDb::new(gains_tenths_db))))
+    }
+
+    pub fn set_tuner_gain_mode(&self, manual_mode: bool) -> Error {
+        to_err(&self.call(|d| d.set_tuner_gain_mode(manual_mode)))
+    }
+
+    pub fn set_sample_rate(&self, rate_hz: i32) -> Error {
+        to_err(&self.call(|d| d.set_sample_rate(SampleRate::new(rate_hz as u32))))
+    }
+
+    pub fn get_sample_rate(&self) -> i32 {
+        self.with_device(|d| d.get_sample_rate().sps() as i32).unwrap_or(0)
+    }
+
+    pub fn set_testmode(&self, test_mode: bool) -> Error {
+        to_err(&self.call(|d| d.set_testmode(test_mode)))
+    }
+
+    pub fn set_agc_mode(&self, agc_mode: bool) -> Error {
+        to_err(&self.call(|d| d.set_agc_mode(agc_mode)))
+    }
+
+    pub fn set_direct_sampling(&self, mode: SamplingMode) -> Error {
+        to_err(&self.call(|d| d.set_direct_sampling(mode)))
+    }
+
+    pub fn get_direct_sampling(&self) -> SamplingMode {
+        self.with_device(|d| d.get_direct_sampling()).unwrap_or(SamplingMode::Error)
+    }
+
+    pub fn set_offset_tuning(&self, enable: bool) -> Error {
+        to_err(&self.call(|d| d.set_offset_tuning(enable)))
+    }
+
+    /// Returns `NoError` when disabled and `Unknown` when enabled, as before.
+    pub fn get_offset_tuning(&self) -> Error {
+        match self.call(|d| d.get_offset_tuning()) {
+            Ok(false) => Error::NoError,
+            Ok(true) => Error::Unknown,
+            Err(ref e) => Error::from(e),
+        }
+    }
+
+    pub fn reset_buffer(&self) -> Error {
+        to_err(&self.call(|d| d.reset_buffer()))
+    }
+
+    pub fn read_sync(&self, len: i32) -> (Vec<u8>, i32, Error) {
+        match self.call(|d| d.read_sync(len)) {
+            Ok(mut buf) => {
+                let n_read = buf.len() as i32;
+                buf.resize(len as usize, 0);
+                (buf, n_read, Error::NoError)
+            }
+            Err(ref e) => (vec![0u8; len.max(0) as usize], 0, Error::from(e)),
+        }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn read_async(&self,
+                      f: ReadAsyncCbT,
+                      ctx: *mut c_void,
+                      buf_num: i32,
+                      buf_len: i32)
+                      -> Error {
+        let dev = self.dev.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        match dev {
+            Some(dev) => to_err(&unsafe { dev.0.read_async_raw(f, ctx, buf_num, buf_len) }),
+            None => Error::NoDevice,
+        }
+    }
+
+    pub fn cancel_async(&self) -> Error {
+        match self.canceller {
+            Some(ref c) => to_err(&c.cancel_async()),
+            None => Error::NoDevice,
+        }
+    }
+
+    pub fn get_hw_info(&self) -> (HwInfo, Error) {
+        match self.call(|d| d.get_hw_info()) {
+            Ok(info) => (info, Error::NoError),
+            Err(ref e) => {
+                (HwInfo {
+                     vendor_id: 0,
+                     product_id: 0,
+                     manufact: String::new(),
+                     product: String::new(),
+                     serial: String::new(),
+                     have_serial: false,
+                     enable_ir: false,
+                     remote_wakeup: false,
+                 },
+                 Error::from(e))
+            }
+        }
+    }
+
+    pub fn set_hw_info(&self, info: &HwInfo) -> Error {
+        to_err(&self.call(|d| d.set_hw_info(info)))
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::error;
+use std::fmt;
+#[cfg(feature = "serde")]
+use std::fs;
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::result;
+
+use super::resampler::nominal_xtal;
+#[cfg(feature = "serde")]
+use super::ErrorKind;
+use super::{Error, Gain, Hertz, Ppm, Result, SampleRate, SamplingMode, SdrSource, TenthDb};
+
+/// A set of device settings, applied in one go with `Device::apply`.
+///
+/// `None` leaves a setting as it is. Build one with the chained setters,
+/// e.g. `DeviceConfig::new().center_freq(freq).gain(Gain::Max)`.
+///
+/// With the `serde` feature it doubles as a TOML device profile. Keys are
+/// the field names, values are in the units' base units (Hz, samples per
+/// second, ppm, tenths of dB), enum values are their variant names and
+/// missing keys are `None`:
+///
+/// ```toml
+/// freq_correction = -3
+/// sample_rate = 2400000
+/// center_freq = 1090000000
+/// gain = { Manual = 297 }
+/// if_gains = [[1, 30], [2, 60]]
+/// agc = false
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DeviceConfig {
+    /// RTL2832 and tuner xtal frequencies. Read back with the ppm
+    /// correction applied, as get_xtal_freq reports them.
+    pub xtal_freq: Option<(Hertz, Hertz)>,
+    pub freq_correction: Option<Ppm>,
+    pub testmode: Option<bool>,
+    pub direct_sampling: Option<SamplingMode>,
+    pub offset_tuning: Option<bool>,
+    pub sample_rate: Option<SampleRate>,
+    pub bandwidth: Option<Hertz>,
+    pub center_freq: Option<Hertz>,
+    pub gain: Option<Gain>,
+    /// IF gains by stage, applied in order.
+    pub if_gains: Vec<(i32, TenthDb)>,
+    pub agc: Option<bool>,
+}
+
+/// A setting `apply` couldn't make.
+#[derive(Debug)]
+pub struct ApplyError {
+    /// The failed setting, named after its `DeviceConfig` field.
+    pub setting: &'static str,
+    pub error: Error,
+    /// Whether the settings applied before the failure were restored. Test
+    /// mode, bandwidth, IF gains and AGC can't be read back, so they can't
+    /// be restored.
+    pub rolled_back: bool,
+    /// The settings made before the failure, in the order they were made.
+    pub applied: Vec<&'static str>,
+    /// The settings in `applied` left as `config` set them, each is restored
+    /// on its own so one failure doesn't keep the rest.
+    pub not_restored: Vec<&'static str>,
+}
+
+impl DeviceConfig {
+    /// Creates a configuration that changes nothing.
+    pub fn new() -> DeviceConfig {
+        DeviceConfig::default()
+    }
+
+    /// Reads the current settings back from `dev`. Settings without a
+    /// getter are left `None`.
+    pub fn read_from<S: SdrSource + ?Sized>(dev: &S) -> DeviceConfig {
+        DeviceConfig {
+            xtal_freq: dev.get_xtal_freq().ok(),
+            freq_correction: Some(dev.get_freq_correction()),
+            testmode: None,
+            direct_sampling: Some(dev.get_direct_sampling()),
+            offset_tuning: dev.get_offset_tuning().ok(),
+            sample_rate: Some(dev.get_sample_rate()),
+            bandwidth: None,
+            center_freq: Some(dev.get_center_freq()),
+            gain: Some(dev.get_gain()),
+            if_gains: Vec::new(),
+            agc: None,
+        }
+    }
+
+    pub fn xtal_freq(mut self, rtl_freq: Hertz, tuner_freq: Hertz) -> DeviceConfig {
+        self.xtal_freq = Some((rtl_freq, tuner_freq));
+        self
+    }
+
+    pub fn freq_correction(mut self, ppm: Ppm) -> DeviceConfig {
+        self.freq_correction = Some(ppm);
+        self
+    }
+
+    pub fn testmode(mut self, test_mode: bool) -> DeviceConfig {
+        self.testmode = Some(test_mode);
+        self
+    }
+
+    pub fn direct_sampling(mut self, mode: SamplingMode) -> DeviceConfig {
+        self.direct_sampling = Some(mode);
+        self
+    }
+
+    pub fn offset_tuning(mut self, enable: bool) -> DeviceConfig {
+        self.offset_tuning = Some(enable);
+        self
+    }
+
+    pub fn sample_rate(mut self, rate: SampleRate) -> DeviceConfig {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    pub fn bandwidth(mut self, bw: Hertz) -> DeviceConfig {
+        self.bandwidth = Some(bw);
+        self
+    }
+
+    pub fn center_freq(mut self, freq: Hertz) -> DeviceConfig {
+        self.center_freq = Some(freq);
+        self
+    }
+
+    pub fn gain(mut self, gain: Gain) -> DeviceConfig {
+        self.gain = Some(gain);
+        self
+    }
+
+    /// Adds an IF gain for `stage`.
+    pub fn if_gain(mut self, stage: i32, gain: TenthDb) -> DeviceConfig {
+        self.if_gains.push((stage, gain));
+        self
+    }
+
+    pub fn agc(mut self, agc_mode: bool) -> DeviceConfig {
+        self.agc = Some(agc_mode);
+        self
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DeviceConfig {
+    /// Parses a TOML device profile.
+    pub fn from_toml(s: &str) -> Result<DeviceConfig> {
+        toml::from_str(s)
+            .map_err(|_| Error::with_kind("DeviceConfig::from_toml", ErrorKind::InvalidParam, -2))
+    }
+
+    /// Renders the configuration as a TOML device profile.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self)
+            .map_err(|_| Error::with_kind("DeviceConfig::to_toml", ErrorKind::InvalidParam, -2))
+    }
+
+    /// Loads a TOML device profile from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DeviceConfig> {
+        let s = fs::read_to_string(path).map_err(|e| Error::from_io("DeviceConfig::load", &e))?;
+        DeviceConfig::from_toml(&s)
+    }
+
+    /// Saves the configuration to a file as a TOML device profile.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_toml()?).map_err(|e| Error::from_io("DeviceConfig::save", &e))
+    }
+}
+
+// Makes the settings in dependency order: the clocks first, then the modes
+// that change what the tuner can do, the rate before the bandwidth it picks
+// a filter for, and the gains once the tuner is tuned. Records each
+// setting made in `applied`.
+fn set_all<S: SdrSource + ?Sized>(dev: &S,
+                                  config: &DeviceConfig,
+                                  applied: &mut Vec<&'static str>)
+                                  -> result::Result<(), (&'static str, Error)> {
+    let mut step = |setting: &'static str, r: Result<()>| {
+        r.map_err(|e| (setting, e))?;
+        applied.push(setting);
+        Ok(())
+    };
+    if let Some((rtl_freq, tuner_freq)) = config.xtal_freq {
+        step("xtal_freq", dev.set_xtal_freq(rtl_freq, tuner_freq))?;
+    }
+    // librtlsdr refuses to set the current correction again.
+    if let Some(ppm) = config.freq_correction.filter(|&ppm| ppm != dev.get_freq_correction()) {
+        step("freq_correction", dev.set_freq_correction(ppm))?;
+    }
+    if let Some(test_mode) = config.testmode {
+        step("testmode", dev.set_testmode(test_mode))?;
+    }
+    if let Some(mode) = config.direct_sampling {
+        step("direct_sampling", dev.set_direct_sampling(mode))?;
+    }
+    // The R82xx refuse offset tuning even when turning it off.
+    let offset_tuning = dev.get_offset_tuning().ok();
+    if let Some(on) = config.offset_tuning.filter(|&on| offset_tuning != Some(on)) {
+        step("offset_tuning", dev.set_offset_tuning(on))?;
+    }
+    if let Some(rate) = config.sample_rate {
+        step("sample_rate", dev.set_sample_rate(rate))?;
+    }
+    if let Some(bw) = config.bandwidth {
+        step("bandwidth", dev.set_tuner_bandwidth(bw))?;
+    }
+    if let Some(freq) = config.center_freq {
+        step("center_freq", dev.set_center_freq(freq))?;
+    }
+    if let Some(gain) = config.gain {
+        step("gain", dev.set_gain(gain).map(|_| ()))?;
+    }
+    for &(stage, gain) in &config.if_gains {
+        step("if_gains", dev.set_tuner_if_gain(stage, gain))?;
+    }
+    if let Some(agc_mode) = config.agc {
+        step("agc", dev.set_agc_mode(agc_mode))?;
+    }
+    Ok(())
+}
+
+/// Applies `config` to `dev`, see `Device::apply`.
+pub(crate) fn apply<S: SdrSource + ?Sized>(dev: &S,
+                                           config: &DeviceConfig)
+                                           -> result::Result<DeviceConfig, ApplyError> {
+    let before = DeviceConfig::read_from(dev);
+    let mut applied = Vec::new();
+    if let Err((setting, error)) = set_all(dev, config, &mut applied) {
+        let not_restored = restore(dev, &before, &applied);
+        return Err(ApplyError {
+            setting,
+            error,
+            rolled_back: not_restored.is_empty(),
+            applied,
+            not_restored,
+        });
+    }
+    Ok(DeviceConfig::read_from(dev))
+}
+
+// Restores the `applied` settings from `before` one at a time, returns the
+// ones that couldn't be.
+fn restore<S: SdrSource + ?Sized>(dev: &S,
+                                  before: &DeviceConfig,
+                                  applied: &[&'static str])
+                                  -> Vec<&'static str> {
+    let ppm = before.freq_correction.unwrap_or_default();
+    let mut not_restored = Vec::new();
+    for &setting in applied {
+        let mut restore = DeviceConfig::new();
+        match setting {
+            "xtal_freq" => {
+                restore.xtal_freq = before.xtal_freq
+                    .map(|(rtl, tuner)| (nominal_xtal(rtl, ppm), nominal_xtal(tuner, ppm)))
+            }
+            "freq_correction" => restore.freq_correction = before.freq_correction,
+            "direct_sampling" => restore.direct_sampling = before.direct_sampling,
+            "offset_tuning" => restore.offset_tuning = before.offset_tuning,
+            "sample_rate" => restore.sample_rate = before.sample_rate,
+            "center_freq" => restore.center_freq = before.center_freq,
+            "gain" => restore.gain = before.gain,
+            _ => {
+                not_restored.push(setting);
+                continue;
+            }
+        }
+        if set_all(dev, &restore, &mut Vec::new()).is_err() {
+            not_restored.push(setting);
+        }
+    }
+    not_restored.dedup();
+    not_restored
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "setting {} failed, {}", self.setting, self.error)?;
+        if !self.not_restored.is_empty() {
+            write!(f, ", {} not restored", self.not_restored.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ApplyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ApplyError> for Error {
+    fn from(e: ApplyError) -> Error {
+        e.error
+    }
+}
@@ -0,0 +1,629 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The dongle's 256 byte configuration EEPROM.
+//!
+//! The RTL2832U reads its USB identity from it at power up:
+//!
+//! | Offset | Contents                                                   |
+//! |--------|------------------------------------------------------------|
+//! | 0x00   | header, 0x28 0x32                                          |
+//! | 0x02   | USB vendor ID, little endian                               |
+//! | 0x04   | USB product ID, little endian                              |
+//! | 0x06   | 0xa5 if the serial number is reported                      |
+//! | 0x07   | configuration, bit 0 remote wakeup, bit 1 IR endpoint     |
+//! | 0x08   | unknown, 0x02 on most dongles                              |
+//! | 0x09   | manufacturer, product and serial USB string descriptors    |
+//!
+//! librtlsdr doesn't use the rest, it's usually erased to 0xff.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::error::{NO_VALID_EEPROM_HEADER, STRING_DESCRIPTOR_INVALID,
+                   STRING_DESCRIPTOR_TOO_LONG, STRING_VALUE_TOO_LONG, TRAILER_NOT_ERASED};
+use super::{Error, ErrorKind, HwInfo, Result, SdrSource, EEPROM_SIZE, MAX_STR_SIZE};
+
+/// The header a valid EEPROM starts with.
+pub const HEADER: [u8; 2] = [0x28, 0x32];
+
+// Where the string descriptors start.
+const STRINGS_OFFSET: usize = 0x09;
+const USB_DT_STRING: u8 = 0x03;
+const HAVE_SERIAL: u8 = 0xa5;
+const REMOTE_WAKEUP: u8 = 0x01;
+const ENABLE_IR: u8 = 0x02;
+const DEFAULT_CONFIG: u8 = 0x14;
+
+/// A parsed EEPROM image.
+///
+/// Any 256 bytes parse, whatever isn't understood is kept as it is, and
+/// `to_bytes` gives back the exact bytes parsed as long as nothing was
+/// changed. Edits only touch the bytes of the fields edited, and of the
+/// strings following an edited string, except on an image without a header
+/// or strings, where setting a string discards the trailer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EepromImage {
+    /// 0x28 0x32 on a valid image.
+    pub header: [u8; 2],
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// 0xa5 if the serial number is reported, see `have_serial`.
+    pub serial_flag: u8,
+    /// Configuration bits, see `remote_wakeup` and `enable_ir`.
+    pub config: u8,
+    /// The byte after the configuration byte, its use is unknown.
+    pub reserved: u8,
+    /// The string descriptors as UTF-16 code units: manufacturer, product
+    /// and serial. Parsing stops at the first invalid descriptor, so an
+    /// image without valid ones has fewer than three.
+    pub strings: Vec<Vec<u16>>,
+    /// Everything after the string descriptors, up to the end of the image.
+    pub trailer: Vec<u8>,
+}
+
+fn invalid(op: &'static str) -> Error {
+    Error::with_kind(op, ErrorKind::InvalidParam, -2)
+}
+
+/// The most UTF-16 code units a USB string descriptor holds, its one byte
+/// length counts the two header bytes as well.
+pub const MAX_DESCRIPTOR_UNITS: usize = 126;
+
+// The code units of the string descriptor at the start of `data` and its
+// length in bytes, `None` unless it's a complete string descriptor.
+fn descriptor_units(data: &[u8]) -> Option<(Vec<u16>, usize)> {
+    let len = *data.first()? as usize;
+    if len < 2 || len % 2 != 0 || len > data.len() || data[1] != USB_DT_STRING {
+        return None;
+    }
+    let units = data[2..len].chunks(2).map(|u| u16::from_le_bytes([u[0], u[1]])).collect();
+    Some((units, len))
+}
+
+/// Decodes the USB string descriptor at the start of `data`, returns the
+/// string and the descriptor's length in bytes.
+///
+/// Fails with `StringDescriptorInvalid` if the descriptor is truncated, its
+/// length is odd or shorter than its header, it isn't a string descriptor
+/// or it isn't valid UTF-16LE.
+pub fn decode_descriptor(data: &[u8]) -> Result<(String, usize)> {
+    let op = "eeprom::decode_descriptor";
+    let (units, len) =
+        descriptor_units(data).ok_or_else(|| Error::new(op, STRING_DESCRIPTOR_INVALID))?;
+    let s = String::from_utf16(&units).map_err(|_| Error::new(op, STRING_DESCRIPTOR_INVALID))?;
+    Ok((s, len))
+}
+
+/// Encodes `s` as a UTF-16LE USB string descriptor.
+///
+/// Fails with `StringDescriptorTooLong` if `s` takes more than
+/// `MAX_DESCRIPTOR_UNITS` UTF-16 code units.
+pub fn encode_descriptor(s: &str) -> Result<Vec<u8>> {
+    let units: Vec<u16> = s.encode_utf16().collect();
+    if units.len() > MAX_DESCRIPTOR_UNITS {
+        return Err(Error::new("eeprom::encode_descriptor", STRING_DESCRIPTOR_TOO_LONG));
+    }
+    let mut data = vec![(units.len() * 2 + 2) as u8, USB_DT_STRING];
+    data.extend(units.iter().flat_map(|u| u.to_le_bytes()));
+    Ok(data)
+}
+
+// Encodes a string as descriptor code units, at most MAX_STR_SIZE of them
+// as librtlsdr allows.
+fn encode(op: &'static str, s: &str) -> Result<Vec<u16>> {
+    let units: Vec<u16> = s.encode_utf16().collect();
+    if units.len() > MAX_STR_SIZE {
+        return Err(Error::new(op, STRING_DESCRIPTOR_TOO_LONG));
+    }
+    Ok(units)
+}
+
+impl EepromImage {
+    /// Parses a full 256 byte image, fails with `InvalidParam` for any
+    /// other length.
+    pub fn parse(data: &[u8]) -> Result<EepromImage> {
+        if data.len() != EEPROM_SIZE as usize {
+            return Err(invalid("EepromImage::parse"));
+        }
+        let mut strings = Vec::new();
+        let mut pos = STRINGS_OFFSET;
+        while strings.len() < 3 {
+            match descriptor_units(&data[pos..]) {
+                Some((units, len)) => {
+                    strings.push(units);
+                    pos += len;
+                }
+                None => break,
+            }
+        }
+        Ok(EepromImage {
+            header: [data[0], data[1]],
+            vendor_id: u16::from_le_bytes([data[2], data[3]]),
+            product_id: u16::from_le_bytes([data[4], data[5]]),
+            serial_flag: data[6],
+            config: data[7],
+            reserved: data[8],
+            strings,
+            trailer: data[pos..].to_vec(),
+        })
+    }
+
+    /// Returns the 256 byte image.
+    ///
+    /// The trailer stays at the end of the image. Strings that shrank leave
+    /// erased (0xff) bytes before it, strings that grew may only take up its
+    /// erased leading bytes. Fails with `TrailerNotErased` if they would
+    /// overwrite other bytes, see `clear_trailer`, with
+    /// `StringDescriptorTooLong` if they don't fit the image or a string
+    /// doesn't fit a descriptor, and with `InvalidParam` if there are more
+    /// than three strings or the trailer is too long.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let op = "EepromImage::to_bytes";
+        let size = EEPROM_SIZE as usize;
+        if self.strings.len() > 3 || self.trailer.len() > size - STRINGS_OFFSET {
+            return Err(invalid(op));
+        }
+        let mut data = Vec::with_capacity(size);
+        data.extend_from_slice(&self.header);
+        data.extend_from_slice(&self.vendor_id.to_le_bytes());
+        data.extend_from_slice(&self.product_id.to_le_bytes());
+        data.extend_from_slice(&[self.serial_flag, self.config, self.reserved]);
+        for s in &self.strings {
+            if s.len() > MAX_DESCRIPTOR_UNITS {
+                return Err(Error::new(op, STRING_DESCRIPTOR_TOO_LONG));
+            }
+            data.extend_from_slice(&[(s.len() * 2 + 2) as u8, USB_DT_STRING]);
+            data.extend(s.iter().flat_map(|u| u.to_le_bytes()));
+        }
+        let start = size - self.trailer.len();
+        let overlap = data.len().saturating_sub(start);
+        if data.len() > size {
+            return Err(Error::new(op, STRING_DESCRIPTOR_TOO_LONG));
+        }
+        if self.trailer[..overlap].iter().any(|&b| b != 0xff) {
+            return Err(Error::new(op, TRAILER_NOT_ERASED));
+        }
+        data.resize(start.max(data.len()), 0xff);
+        data.extend_from_slice(&self.trailer[overlap..]);
+        Ok(data)
+    }
+
+    /// Discards the bytes after the strings, `to_bytes` erases them. Lets
+    /// longer strings overwrite bytes that aren't erased, e.g. a zero filled
+    /// EEPROM.
+    pub fn clear_trailer(&mut self) {
+        self.trailer.clear();
+    }
+
+    // An image without a header or strings holds nothing after its strings
+    // worth keeping, e.g. an all zero EEPROM, so the strings may use all of
+    // it.
+    fn clear_blank_trailer(&mut self) {
+        if !self.has_valid_header() || self.strings.is_empty() {
+            self.clear_trailer();
+        }
+    }
+
+    // Where the strings end and the trailer starts when it directly follows.
+    fn strings_end(&self) -> usize {
+        STRINGS_OFFSET + self.strings.iter().map(|s| s.len() * 2 + 2).sum::<usize>()
+    }
+
+    /// Renders the image as 16 bytes per line hex, each line prefixed with
+    /// its offset.
+    pub fn hexdump(&self) -> Result<String> {
+        Ok(self.to_bytes()?
+            .chunks(16)
+            .enumerate()
+            .map(|(i, line)| format!("0x{:02x}: {}\n", i * 16, hex(line)))
+            .collect())
+    }
+
+    /// Whether the header is the one the RTL2832U looks for.
+    pub fn has_valid_header(&self) -> bool {
+        self.header == HEADER
+    }
+
+    /// Whether the dongle reports its serial number.
+    pub fn have_serial(&self) -> bool {
+        self.serial_flag == HAVE_SERIAL
+    }
+
+    /// Sets the serial flag, leaving it as it is if it already says `on`.
+    pub fn set_have_serial(&mut self, on: bool) {
+        if self.have_serial() != on {
+            self.serial_flag = if on { HAVE_SERIAL } else { 0x00 };
+        }
+    }
+
+    pub fn remote_wakeup(&self) -> bool {
+        self.config & REMOTE_WAKEUP != 0
+    }
+
+    pub fn set_remote_wakeup(&mut self, on: bool) {
+        self.set_config_bit(REMOTE_WAKEUP, on);
+    }
+
+    /// Whether the IR receiver endpoint is enabled.
+    pub fn enable_ir(&self) -> bool {
+        self.config & ENABLE_IR != 0
+    }
+
+    pub fn set_enable_ir(&mut self, on: bool) {
+        self.set_config_bit(ENABLE_IR, on);
+    }
+
+    fn set_config_bit(&mut self, bit: u8, on: bool) {
+        if on {
+            self.config |= bit;
+        } else {
+            self.config &= !bit;
+        }
+    }
+
+    // The string at `i` for display, unpaired surrogates replaced, `None` if
+    // the image doesn't have it.
+    fn string(&self, i: usize) -> Option<String> {
+        self.strings.get(i).map(|s| String::from_utf16_lossy(s))
+    }
+
+    // Sets the string at `i`, adding empty strings before it as needed.
+    fn set_string(&mut self, i: usize, s: &str) -> Result<()> {
+        let units = encode("EepromImage::set_string", s)?;
+        self.clear_blank_trailer();
+        if self.strings.len() <= i {
+            self.strings.resize(i + 1, Vec::new());
+        }
+        self.strings[i] = units;
+        Ok(())
+    }
+
+    pub fn manufacturer(&self) -> Option<String> {
+        self.string(0)
+    }
+
+    /// Sets the manufacturer string, at most 35 UTF-16 code units.
+    pub fn set_manufacturer(&mut self, s: &str) -> Result<()> {
+        self.set_string(0, s)
+    }
+
+    pub fn product(&self) -> Option<String> {
+        self.string(1)
+    }
+
+    /// Sets the product string, at most 35 UTF-16 code units.
+    pub fn set_product(&mut self, s: &str) -> Result<()> {
+        self.set_string(1, s)
+    }
+
+    pub fn serial(&self) -> Option<String> {
+        self.string(2)
+    }
+
+    /// Sets the serial number string, at most 35 UTF-16 code units.
+    pub fn set_serial(&mut self, s: &str) -> Result<()> {
+        self.set_string(2, s)
+    }
+
+    /// Returns the dongle information the image holds, as `get_hw_info`
+    /// does. Fails with `StringDescriptorInvalid` if a string isn't valid
+    /// UTF-16.
+    pub fn hw_info(&self) -> Result<HwInfo> {
+        let op = "get_hw_info";
+        if !self.has_valid_header() {
+            return Err(Error::new(op, NO_VALID_EEPROM_HEADER));
+        }
+        if self.strings.len() < 3 {
+            return Err(Error::new(op, STRING_DESCRIPTOR_INVALID));
+        }
+        if self.strings.iter().any(|s| s.len() > MAX_STR_SIZE) {
+            return Err(Error::new(op, STRING_VALUE_TOO_LONG));
+        }
+        let string = |i: usize| {
+            String::from_utf16(&self.strings[i])
+                .map_err(|_| Error::new(op, STRING_DESCRIPTOR_INVALID))
+        };
+        Ok(HwInfo {
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            manufact: string(0)?,
+            product: string(1)?,
+            serial: string(2)?,
+            have_serial: self.have_serial(),
+            enable_ir: self.enable_ir(),
+            remote_wakeup: self.remote_wakeup(),
+        })
+    }
+
+    /// Sets the fields `info` covers and the header, leaving the rest of the
+    /// image as it is. The bytes after the strings of an image without a
+    /// header or strings are discarded.
+    pub fn set_hw_info(&mut self, info: &HwInfo) -> Result<()> {
+        let op = "set_hw_info";
+        let strings = vec![encode(op, &info.manufact)?,
+                           encode(op, &info.product)?,
+                           encode(op, &info.serial)?];
+        self.clear_blank_trailer();
+        self.header = HEADER;
+        self.vendor_id = info.vendor_id;
+        self.product_id = info.product_id;
+        self.set_have_serial(info.have_serial);
+        self.set_remote_wakeup(info.remote_wakeup);
+        self.set_enable_ir(info.enable_ir);
+        self.strings = strings;
+        Ok(())
+    }
+}
+
+/// A known dongle configuration, see `PRESETS`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preset {
+    /// The name to look it up with, as rtl_eeprom's -g takes it.
+    pub name: &'static str,
+    pub description: &'static str,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub manufacturer: &'static str,
+    pub product: &'static str,
+    pub enable_ir: bool,
+    pub remote_wakeup: bool,
+}
+
+/// Factory configurations of common dongles, the first five as the C
+/// rtl_eeprom generates them.
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "realtek",
+        description: "Realtek default, as dongles without an EEPROM report",
+        vendor_id: 0x0bda,
+        product_id: 0x2832,
+        manufacturer: "Generic",
+        product: "RTL2832U DVB-T",
+        enable_ir: false,
+        remote_wakeup: true,
+    },
+    Preset {
+        name: "realtek_oem",
+        description: "Realtek OEM, as on most generic dongles",
+        vendor_id: 0x0bda,
+        product_id: 0x2838,
+        manufacturer: "Realtek",
+        product: "RTL2838UHIDIR",
+        enable_ir: true,
+        remote_wakeup: false,
+    },
+    Preset {
+        name: "noxon",
+        description: "Terratec NOXON DAB/DAB+ USB dongle (rev 1)",
+        vendor_id: 0x0ccd,
+        product_id: 0x00b3,
+        manufacturer: "NOXON",
+        product: "DAB Stick",
+        enable_ir: false,
+        remote_wakeup: true,
+    },
+    Preset {
+        name: "terratec_black",
+        description: "Terratec Cinergy T Stick Black (rev 1)",
+        vendor_id: 0x0ccd,
+        product_id: 0x00a9,
+        manufacturer: "Realtek",
+        product: "RTL2838UHIDIR",
+        enable_ir: true,
+        remote_wakeup: false,
+    },
+    Preset {
+        name: "terratec_plus",
+        description: "Terratec T Stick PLUS",
+        vendor_id: 0x0ccd,
+        product_id: 0x00d7,
+        manufacturer: "Realtek",
+        product: "RTL2838UHIDIR",
+        enable_ir: true,
+        remote_wakeup: false,
+    },
+    Preset {
+        name: "rtlsdr_blog_v3",
+        description: "RTL-SDR Blog V3",
+        vendor_id: 0x0bda,
+        product_id: 0x2838,
+        manufacturer: "RTLSDRBlog",
+        product: "Blog V3",
+        enable_ir: true,
+        remote_wakeup: false,
+    },
+    Preset {
+        name: "rtlsdr_blog_v4",
+        description: "RTL-SDR Blog V4",
+        vendor_id: 0x0bda,
+        product_id: 0x2838,
+        manufacturer: "RTLSDRBlog",
+        product: "Blog V4",
+        enable_ir: true,
+        remote_wakeup: false,
+    },
+];
+
+/// Looks a preset up by name, ignoring case.
+pub fn preset(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+impl Preset {
+    /// Returns the full EEPROM image the preset describes, with serial
+    /// number `serial` and everything after the strings erased.
+    pub fn image(&self, serial: &str) -> Result<EepromImage> {
+        let mut image = EepromImage {
+            header: HEADER,
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            serial_flag: HAVE_SERIAL,
+            // The values rtl_eeprom writes.
+            config: DEFAULT_CONFIG,
+            reserved: 0x02,
+            strings: Vec::new(),
+            trailer: Vec::new(),
+        };
+        image.set_manufacturer(self.manufacturer)?;
+        image.set_product(self.product)?;
+        image.set_serial(serial)?;
+        image.set_enable_ir(self.enable_ir);
+        image.set_remote_wakeup(self.remote_wakeup);
+        image.trailer = vec![0xff; EEPROM_SIZE as usize - image.strings_end()];
+        Ok(image)
+    }
+}
+
+/// Returns the image `apply_preset` would write to `dev`: the preset with
+/// the dongle's current serial number and the bytes after its strings.
+pub fn render_preset<S: SdrSource + ?Sized>(dev: &S, preset: &Preset) -> Result<EepromImage> {
+    let current = read_image(dev)?;
+    let mut image = preset.image(&current.serial().unwrap_or_default())?;
+    image.trailer = current.trailer;
+    Ok(image)
+}
+
+/// Writes `preset` to the EEPROM of `dev`, keeping its serial number and
+/// the bytes after the strings, and verifies the write. Returns the image
+/// written.
+pub fn apply_preset<S: SdrSource + ?Sized>(dev: &S, preset: &Preset) -> Result<EepromImage> {
+    let image = render_preset(dev, preset)?;
+    write_verified(dev, &image, false)?;
+    Ok(image)
+}
+
+/// A field that differs between two images, see `diff`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+// A string field for display, quoted, or "none" if the image lacks it.
+fn quoted(s: Option<String>) -> String {
+    s.map_or_else(|| "none".to_string(), |s| format!("{:?}", s))
+}
+
+/// Lists the fields that differ from `old` to `new`, in EEPROM order.
+///
+/// Bytes after the strings are compared where they sit in the EEPROM and
+/// reported as one "trailer" field spanning the differing bytes.
+pub fn diff(old: &EepromImage, new: &EepromImage) -> Vec<FieldDiff> {
+    let mut diffs = Vec::new();
+    let mut field = |field: &'static str, old: String, new: String| {
+        if old != new {
+            diffs.push(FieldDiff { field, old, new });
+        }
+    };
+    field("header", hex(&old.header), hex(&new.header));
+    field("vendor_id",
+          format!("0x{:04x}", old.vendor_id),
+          format!("0x{:04x}", new.vendor_id));
+    field("product_id",
+          format!("0x{:04x}", old.product_id),
+          format!("0x{:04x}", new.product_id));
+    field("serial_flag",
+          format!("0x{:02x}", old.serial_flag),
+          format!("0x{:02x}", new.serial_flag));
+    field("config", format!("0x{:02x}", old.config), format!("0x{:02x}", new.config));
+    field("reserved",
+          format!("0x{:02x}", old.reserved),
+          format!("0x{:02x}", new.reserved));
+    field("manufacturer", quoted(old.manufacturer()), quoted(new.manufacturer()));
+    field("product", quoted(old.product()), quoted(new.product()));
+    field("serial", quoted(old.serial()), quoted(new.serial()));
+    // Compare where the trailers overlap, the rest is covered by the strings.
+    let size = EEPROM_SIZE as usize;
+    let n = old.trailer.len().min(new.trailer.len()).min(size);
+    let a = &old.trailer[old.trailer.len() - n..];
+    let b = &new.trailer[new.trailer.len() - n..];
+    if let Some(first) = a.iter().zip(b).position(|(x, y)| x != y) {
+        let last = a.iter().zip(b).rposition(|(x, y)| x != y).unwrap_or(first);
+        let offset = size - n + first;
+        field("trailer",
+              format!("{} at 0x{:02x}", hex(&a[first..=last]), offset),
+              format!("{} at 0x{:02x}", hex(&b[first..=last]), offset));
+    }
+    diffs
+}
+
+/// Reads the full EEPROM of `dev` and saves it to the file at `path`,
+/// returns the image saved.
+pub fn dump<S: SdrSource + ?Sized, P: AsRef<Path>>(dev: &S, path: P) -> Result<EepromImage> {
+    let data = dev.read_eeprom(0, EEPROM_SIZE as u16)?;
+    fs::write(path, &data).map_err(|e| Error::from_io("eeprom::dump", &e))?;
+    EepromImage::parse(&data)
+}
+
+/// Loads an image saved by `dump`, fails with `InvalidParam` if the file
+/// isn't 256 bytes.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<EepromImage> {
+    let data = fs::read(path).map_err(|e| Error::from_io("eeprom::load", &e))?;
+    EepromImage::parse(&data)
+}
+
+/// Writes `image` to the EEPROM of `dev` and reads it back to verify.
+///
+/// A dongle whose EEPROM lacks the 0x28 0x32 header no longer enumerates
+/// with its own USB identity, so an image without it is refused with
+/// `NoValidEEPROMHeader` unless `force` is set. Fails with `Io` if the
+/// EEPROM reads back different.
+pub fn write_verified<S: SdrSource + ?Sized>(dev: &S, image: &EepromImage, force: bool)
+                                            -> Result<()> {
+    let op = "eeprom::write_verified";
+    if !image.has_valid_header() && !force {
+        return Err(Error::new(op, NO_VALID_EEPROM_HEADER));
+    }
+    let data = image.to_bytes()?;
+    dev.write_eeprom(data.clone(), 0)?;
+    if dev.read_eeprom(0, EEPROM_SIZE as u16)? != data {
+        return Err(Error::with_kind(op, ErrorKind::Io, -1));
+    }
+    Ok(())
+}
+
+/// Restores the EEPROM of `dev` from a file saved by `dump`, see
+/// `write_verified`. Returns the image restored.
+pub fn restore<S: SdrSource + ?Sized, P: AsRef<Path>>(dev: &S, path: P, force: bool)
+                                                     -> Result<EepromImage> {
+    let image = load(path)?;
+    write_verified(dev, &image, force)?;
+    Ok(image)
+}
+
+/// Reads and parses the full EEPROM of `dev`.
+pub(crate) fn read_image<S: SdrSource + ?Sized>(dev: &S) -> Result<EepromImage> {
+    EepromImage::parse(&dev.read_eeprom(0, EEPROM_SIZE as u16)?)
+}
+
+/// Writes `image` to the EEPROM of `dev`.
+pub(crate) fn write_image<S: SdrSource + ?Sized>(dev: &S, image: &EepromImage) -> Result<()> {
+    dev.write_eeprom(image.to_bytes()?, 0)
+}
+
+/// Updates the dongle information in the EEPROM of `dev`, see
+/// `EepromImage::set_hw_info`.
+pub(crate) fn set_hw_info<S: SdrSource + ?Sized>(dev: &S, info: &HwInfo) -> Result<()> {
+    let mut image = read_image(dev)?;
+    image.set_hw_info(info)?;
+    write_image(dev, &image)
+}
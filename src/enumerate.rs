@@ -0,0 +1,212 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+use super::error::AMBIGUOUS_MATCH;
+use super::ffi;
+use super::{get_device_count, get_device_name, get_device_usb_strings, open, Device, Error,
+            ErrorKind, Result, TenthDb, TunerType};
+
+/// A detected dongle, see `devices`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    /// The index to `open` the dongle with.
+    pub index: i32,
+    /// librtlsdr's name for the dongle model, e.g. "Generic RTL2832U OEM".
+    pub name: String,
+    pub manufacturer: String,
+    pub product: String,
+    pub serial: String,
+    /// USB vendor and product IDs, a best guess from `name` as librtlsdr
+    /// doesn't report them, see `usb_ids`. `None` if librtlsdr knows a model
+    /// this crate doesn't.
+    pub usb_ids: Option<(u16, u16)>,
+    /// The tuner, filled in by `probe_devices`.
+    pub tuner: Option<TunerType>,
+    /// The manual gain steps, filled in by `probe_devices`.
+    pub gains: Option<Vec<TenthDb>>,
+}
+
+// librtlsdr's known_devices table, it only enumerates these and names them
+// by their USB IDs.
+const KNOWN_DEVICES: &[(u16, u16, &str)] = &[
+    (0x0bda, 0x2832, "Generic RTL2832U"),
+    (0x0bda, 0x2838, "Generic RTL2832U OEM"),
+    (0x0413, 0x6680, "DigitalNow Quad DVB-T PCI-E card"),
+    (0x0413, 0x6f0f, "Leadtek WinFast DTV Dongle mini D"),
+    (0x0458, 0x707f, "Genius TVGo DVB-T03 USB dongle (Ver. B)"),
+    (0x0ccd, 0x00a9, "Terratec Cinergy T Stick Black (rev 1)"),
+    (0x0ccd, 0x00b3, "Terratec NOXON DAB/DAB+ USB dongle (rev 1)"),
+    (0x0ccd, 0x00b4, "Terratec Deutschlandradio DAB Stick"),
+    (0x0ccd, 0x00b5, "Terratec NOXON DAB Stick - Radio Energy"),
+    (0x0ccd, 0x00b7, "Terratec Media Broadcast DAB Stick"),
+    (0x0ccd, 0x00b8, "Terratec BR DAB Stick"),
+    (0x0ccd, 0x00b9, "Terratec WDR DAB Stick"),
+    (0x0ccd, 0x00c0, "Terratec MuellerVerlag DAB Stick"),
+    (0x0ccd, 0x00c6, "Terratec Fraunhofer DAB Stick"),
+    (0x0ccd, 0x00d3, "Terratec Cinergy T Stick RC (Rev.3)"),
+    (0x0ccd, 0x00d7, "Terratec T Stick PLUS"),
+    (0x0ccd, 0x00e0, "Terratec NOXON DAB/DAB+ USB dongle (rev 2)"),
+    (0x1554, 0x5020, "PixelView PV-DT235U(RN)"),
+    (0x15f4, 0x0131, "Astrometa DVB-T/DVB-T2"),
+    (0x15f4, 0x0133, "HanfTek DAB+FM+DVB-T"),
+    (0x185b, 0x0620, "Compro Videomate U620F"),
+    (0x185b, 0x0650, "Compro Videomate U650F"),
+    (0x185b, 0x0680, "Compro Videomate U680F"),
+    (0x1b80, 0xd393, "GIGABYTE GT-U7300"),
+    (0x1b80, 0xd394, "DIKOM USB-DVBT HD"),
+    (0x1b80, 0xd395, "Peak 102569AGPK"),
+    (0x1b80, 0xd397, "KWorld KW-UB450-T USB DVB-T Pico TV"),
+    (0x1b80, 0xd398, "Zaapa ZT-MINDVBZP"),
+    (0x1b80, 0xd39d, "SVEON STV20 DVB-T USB & FM"),
+    (0x1b80, 0xd3a4, "Twintech UT-40"),
+    (0x1b80, 0xd3a8, "ASUS U3100MINI_PLUS_V2"),
+    (0x1b80, 0xd3af, "SVEON STV27 DVB-T USB & FM"),
+    (0x1b80, 0xd3b0, "SVEON STV21 DVB-T USB & FM"),
+    (0x1d19, 0x1101, "Dexatek DK DVB-T Dongle (Logilink VG0002A)"),
+    (0x1d19, 0x1102, "Dexatek DK DVB-T Dongle (MSI DigiVox mini II V3.0)"),
+    (0x1d19, 0x1103, "Dexatek Technology Ltd. DK 5217 DVB-T Dongle"),
+    (0x1d19, 0x1104, "MSI DigiVox Micro HD"),
+    (0x1f4d, 0xa803, "Sweex DVB-T USB"),
+    (0x1f4d, 0xb803, "GTek T803"),
+    (0x1f4d, 0xc803, "Lifeview LV5TDeluxe"),
+    (0x1f4d, 0xd286, "MyGica TD312"),
+    (0x1f4d, 0xd803, "PROlectrix DV107669"),
+];
+
+/// Returns the USB vendor and product IDs of the model librtlsdr calls
+/// `name`.
+pub fn usb_ids(name: &str) -> Option<(u16, u16)> {
+    KNOWN_DEVICES.iter().find(|d| d.2 == name).map(|&(vid, pid, _)| (vid, pid))
+}
+
+/// Lists the detected dongles without opening them. Dongles whose USB
+/// strings can't be read, e.g. for lack of permissions, are listed with
+/// empty ones. Fails only if librtlsdr can't be loaded, see `library_info`.
+pub fn devices() -> Result<Vec<DeviceInfo>> {
+    ffi::load()?;
+    (0..get_device_count())
+        .map(|index| {
+            let name = get_device_name(index);
+            let (manufacturer, product, serial) =
+                get_device_usb_strings(index).unwrap_or_default();
+            let usb_ids = usb_ids(&name);
+            Ok(DeviceInfo {
+                index,
+                name,
+                manufacturer,
+                product,
+                serial,
+                usb_ids,
+                tuner: None,
+                gains: None,
+            })
+        })
+        .collect()
+}
+
+/// Lists the detected dongles like `devices`, briefly opening each one to
+/// fill in its tuner and gain steps. Dongles that can't be opened, e.g.
+/// because another process has them, are listed without.
+pub fn probe_devices() -> Result<Vec<DeviceInfo>> {
+    let mut infos = devices()?;
+    for info in &mut infos {
+        if let Ok(dev) = open(info.index) {
+            info.tuner = Some(dev.get_tuner_type());
+            info.gains = dev.get_tuner_gains().ok();
+            let _ = dev.close();
+        }
+    }
+    Ok(infos)
+}
+
+// Opens the one dongle `matches` picks, dongles are told apart by what
+// librtlsdr reports without opening them.
+fn open_matching<F>(op: &'static str, matches: F) -> Result<Device>
+    where F: Fn(&DeviceInfo) -> bool
+{
+    let found: Vec<DeviceInfo> = devices()?.into_iter().filter(|d| matches(d)).collect();
+    match found.len() {
+        0 => Err(Error::with_kind(op, ErrorKind::NotFound, -5)),
+        1 => open(found[0].index),
+        _ => Err(Error::new(op, AMBIGUOUS_MATCH)),
+    }
+}
+
+/// Opens the dongle with serial number `serial`.
+///
+/// Fails with `NotFound` if no dongle has it, `Ambiguous` if several do and
+/// `Busy` if it's in use. Unlike `get_index_by_serial` it won't pick the
+/// first of several dongles sharing a serial.
+pub fn open_by_serial(serial: &str) -> Result<Device> {
+    open_matching("open_by_serial", |d| d.serial == serial)
+}
+
+/// Opens the dongle whose name, manufacturer or product string contains
+/// `pattern`, ignoring case.
+///
+/// Fails with `NotFound` if none matches, `Ambiguous` if several do and
+/// `Busy` if the match is in use.
+pub fn open_by_product_match(pattern: &str) -> Result<Device> {
+    let pattern = pattern.to_lowercase();
+    open_matching("open_by_product_match", |d| {
+        [&d.name, &d.manufacturer, &d.product].iter().any(|s| s.to_lowercase().contains(&pattern))
+    })
+}
+
+// The sysfs name of a USB device, "<bus>-<port>[.<port>...]".
+#[cfg(target_os = "linux")]
+fn is_usb_path(path: &str) -> bool {
+    let mut parts = path.splitn(2, '-');
+    let bus = parts.next().unwrap_or("");
+    let ports = parts.next().unwrap_or("");
+    let number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    number(bus) && ports.split('.').all(number)
+}
+
+// Reads a sysfs attribute, empty if the device doesn't have it.
+#[cfg(target_os = "linux")]
+fn sysfs_attr(dir: &Path, name: &str) -> String {
+    fs::read_to_string(dir.join(name)).map(|s| s.trim_end().to_string()).unwrap_or_default()
+}
+
+/// Opens the dongle plugged into the USB port `path`, named as sysfs and
+/// `lsusb -t` name ports, "<bus>-<port>[.<port>...]", e.g. "1-1.2".
+///
+/// librtlsdr can't open by port, the dongle's IDs and strings are read from
+/// /sys/bus/usb/devices and matched against `devices`. Fails with
+/// `InvalidParam` for a malformed path, `NotFound` if nothing librtlsdr
+/// knows is plugged in there, `Ambiguous` if another dongle has the same
+/// strings, give them distinct serials then, and `Busy` if it's in use.
+#[cfg(target_os = "linux")]
+pub fn open_by_usb_path(path: &str) -> Result<Device> {
+    let op = "open_by_usb_path";
+    if !is_usb_path(path) {
+        return Err(Error::with_kind(op, ErrorKind::InvalidParam, -2));
+    }
+    let dir = Path::new("/sys/bus/usb/devices").join(path);
+    let id = |name| u16::from_str_radix(&sysfs_attr(&dir, name), 16).ok();
+    let (vendor_id, product_id) = match (id("idVendor"), id("idProduct")) {
+        (Some(vid), Some(pid)) => (vid, pid),
+        _ => return Err(Error::with_kind(op, ErrorKind::NotFound, -5)),
+    };
+    let manufacturer = sysfs_attr(&dir, "manufacturer");
+    let product = sysfs_attr(&dir, "product");
+    let serial = sysfs_attr(&dir, "serial");
+    // The IDs `devices` gives are guessed, only rule out a known mismatch.
+    open_matching(op, |d| {
+        d.usb_ids.map_or(true, |ids| ids == (vendor_id, product_id)) &&
+        d.manufacturer == manufacturer && d.product == product && d.serial == serial
+    })
+}
@@ -0,0 +1,199 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+use std::result;
+
+/// Get/set hardware info errors.
+pub(crate) const NO_VALID_EEPROM_HEADER: i32 = -13;
+pub(crate) const STRING_VALUE_TOO_LONG: i32 = -14;
+pub(crate) const STRING_DESCRIPTOR_INVALID: i32 = -15;
+pub(crate) const STRING_DESCRIPTOR_TOO_LONG: i32 = -16;
+pub(crate) const TRAILER_NOT_ERASED: i32 = -18;
+
+/// Device lookup errors.
+pub(crate) const AMBIGUOUS_MATCH: i32 = -17;
+
+//
+pub(crate) const ERROR_UNKNOWN: i32 = -98;
+pub(crate) const LIBUSB_ERROR_UNKNOWN: i32 = -99;
+
+/// A specialized `Result` type for librtlsdr operations.
+pub type Result<T> = result::Result<T, Error>;
+
+/// The general category of an `Error`.
+///
+/// The libusb derived kinds (`Io` through `NotSupported`) mirror the
+/// `LIBUSB_ERROR_*` codes returned by librtlsdr.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorKind {
+    Io,
+    InvalidParam,
+    Access,
+    NoDevice,
+    NotFound,
+    Busy,
+    Timeout,
+    Overflow,
+    Pipe,
+    Interrupted,
+    NoMem,
+    NotSupported,
+    NoValidEEPROMHeader,
+    StringValueTooLong,
+    StringDescriptorInvalid,
+    StringDescriptorTooLong,
+    /// Longer strings would overwrite EEPROM bytes after the strings that
+    /// aren't erased, see `EepromImage::clear_trailer`.
+    TrailerNotErased,
+    /// More than one device matched where one was expected.
+    Ambiguous,
+    Unknown,
+}
+
+impl ErrorKind {
+    /// Maps a raw librtlsdr/libusb return code to an error kind.
+    pub fn from_code(code: i32) -> ErrorKind {
+        match code {
+            -1 => ErrorKind::Io,
+            -2 => ErrorKind::InvalidParam,
+            -3 => ErrorKind::Access,
+            -4 => ErrorKind::NoDevice,
+            -5 => ErrorKind::NotFound,
+            -6 => ErrorKind::Busy,
+            -7 => ErrorKind::Timeout,
+            -8 => ErrorKind::Overflow,
+            -9 => ErrorKind::Pipe,
+            -10 => ErrorKind::Interrupted,
+            -11 => ErrorKind::NoMem,
+            -12 => ErrorKind::NotSupported,
+            NO_VALID_EEPROM_HEADER => ErrorKind::NoValidEEPROMHeader,
+            STRING_VALUE_TOO_LONG => ErrorKind::StringValueTooLong,
+            STRING_DESCRIPTOR_INVALID => ErrorKind::StringDescriptorInvalid,
+            STRING_DESCRIPTOR_TOO_LONG => ErrorKind::StringDescriptorTooLong,
+            TRAILER_NOT_ERASED => ErrorKind::TrailerNotErased,
+            AMBIGUOUS_MATCH => ErrorKind::Ambiguous,
+            _ => ErrorKind::Unknown,
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match *self {
+            ErrorKind::Io => "input/output error",
+            ErrorKind::InvalidParam => "invalid parameter",
+            ErrorKind::Access => "access denied",
+            ErrorKind::NoDevice => "no such device",
+            ErrorKind::NotFound => "entity not found",
+            ErrorKind::Busy => "resource busy",
+            ErrorKind::Timeout => "operation timed out",
+            ErrorKind::Overflow => "overflow",
+            ErrorKind::Pipe => "pipe error",
+            ErrorKind::Interrupted => "system call interrupted",
+            ErrorKind::NoMem => "insufficient memory",
+            ErrorKind::NotSupported => "operation not supported",
+            ErrorKind::NoValidEEPROMHeader => "no valid EEPROM header",
+            ErrorKind::StringValueTooLong => "string value too long",
+            ErrorKind::StringDescriptorInvalid => "string descriptor invalid",
+            ErrorKind::StringDescriptorTooLong => "string descriptor too long",
+            ErrorKind::TrailerNotErased => "would overwrite non-erased trailer bytes",
+            ErrorKind::Ambiguous => "more than one device matches",
+            ErrorKind::Unknown => "unknown error",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// The error type for librtlsdr operations.
+///
+/// Carries the raw return code and the name of the operation that failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Error {
+    kind: ErrorKind,
+    code: i32,
+    op: Cow<'static, str>,
+}
+
+impl Error {
+    /// Creates an error from the raw return code of the named operation.
+    pub fn new(op: &'static str, code: i32) -> Error {
+        Error {
+            kind: ErrorKind::from_code(code),
+            code,
+            op: Cow::Borrowed(op),
+        }
+    }
+
+    /// Creates an error of an explicit kind, for codes that don't follow the
+    /// libusb numbering.
+    pub(crate) fn with_kind(op: &'static str, kind: ErrorKind, code: i32) -> Error {
+        Error {
+            kind,
+            code,
+            op: Cow::Borrowed(op),
+        }
+    }
+
+    /// Creates an error from an I/O failure of the named operation, the code
+    /// is the OS error number when there is one.
+    pub(crate) fn from_io(op: &'static str, err: &io::Error) -> Error {
+        let kind = match err.kind() {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::Access,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ErrorKind::Timeout,
+            io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidParam,
+            io::ErrorKind::BrokenPipe => ErrorKind::Pipe,
+            _ => ErrorKind::Io,
+        };
+        Error::with_kind(op, kind, err.raw_os_error().unwrap_or(-1))
+    }
+
+    /// Returns the error category.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the raw librtlsdr/libusb return code.
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+
+    /// Returns the name of the operation that failed.
+    pub fn op(&self) -> &str {
+        &self.op
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.op, self.kind, self.code)
+    }
+}
+
+impl error::Error for Error {}
+
+/// Converts a librtlsdr return code to a `Result`, negative codes are errors.
+pub(crate) fn check(op: &'static str, code: c_int) -> Result<c_int> {
+    if code < 0 {
+        Err(Error::new(op, code))
+    } else {
+        Ok(code)
+    }
+}
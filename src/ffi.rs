@@ -0,0 +1,348 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The librtlsdr functions, linked or, with the `dynamic` feature, loaded
+//! at runtime.
+//!
+//! Linked, the functions every librtlsdr build has are bound at link time.
+//! Loaded, librtlsdr is opened on first use, from `$RTLSDR_LIBRARY` if it's
+//! set or else by its usual names, or explicitly with `load_library`. Either
+//! way the optional functions, which only some forks have, are looked up
+//! when called and fail with `NotSupported` if the library doesn't have
+//! them, so one binary works against any installed librtlsdr. Linked, they
+//! are looked up in the running process, on unix only.
+
+#[cfg(feature = "dynamic")]
+use std::env;
+#[cfg(feature = "dynamic")]
+use std::ffi::OsString;
+#[cfg(feature = "dynamic")]
+use std::fs;
+use std::os::raw::{c_char, c_int, c_void};
+#[cfg(feature = "dynamic")]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(feature = "dynamic")]
+use std::sync::{Mutex, OnceLock};
+
+#[cfg(any(unix, feature = "dynamic"))]
+use libloading::Library;
+
+use super::{Error, ErrorKind, RTLSDRDevT, ReadAsyncCbT, Result};
+
+/// The optional librtlsdr functions `library_info` looks for.
+///
+/// `rtlsdr_set_bias_tee` and `rtlsdr_set_bias_tee_gpio` are in the
+/// rtl-sdr-blog fork and newer osmocom releases, `rtlsdr_set_dithering` in
+/// the rtl-sdr-blog fork and the GPIO and tuner I2C calls in the extended
+/// forks descending from keenerd's.
+pub const OPTIONAL_FUNCTIONS: &[&str] = &["rtlsdr_set_bias_tee",
+                                          "rtlsdr_set_bias_tee_gpio",
+                                          "rtlsdr_set_dithering",
+                                          "rtlsdr_set_gpio_output",
+                                          "rtlsdr_set_gpio_bit",
+                                          "rtlsdr_get_gpio_bit",
+                                          "rtlsdr_set_tuner_i2c_register",
+                                          "rtlsdr_get_tuner_i2c_register"];
+
+/// What's known about the librtlsdr in use, see `library_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LibraryInfo {
+    /// Whether librtlsdr was loaded at runtime rather than linked.
+    pub dynamic: bool,
+    /// The file librtlsdr was loaded from, a bare name if the dynamic
+    /// loader searched for it, `None` when linked.
+    pub path: Option<PathBuf>,
+    /// The version in the library's file name, e.g. "0.6.0" for
+    /// librtlsdr.so.0.6.0, librtlsdr can't report its own.
+    pub version: Option<String>,
+    /// The `OPTIONAL_FUNCTIONS` the library has.
+    pub functions: Vec<&'static str>,
+}
+
+impl LibraryInfo {
+    /// Whether the library has the optional `function`.
+    pub fn has(&self, function: &str) -> bool {
+        self.functions.contains(&function)
+    }
+}
+
+// Declares the functions every librtlsdr has, as an extern block when
+// linked and as wrappers around the loaded symbols otherwise.
+macro_rules! functions {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        #[cfg(not(feature = "dynamic"))]
+        #[link(name = "rtlsdr")]
+        extern "C" {
+            $(pub(crate) fn $name($($arg: $ty),*) -> $ret;)*
+        }
+
+        #[cfg(feature = "dynamic")]
+        struct Functions {
+            $($name: unsafe extern "C" fn($($ty),*) -> $ret,)*
+        }
+
+        #[cfg(feature = "dynamic")]
+        impl Functions {
+            // Fails with the name of the first missing function.
+            unsafe fn load(lib: &Library) -> ::std::result::Result<Functions, &'static str> {
+                Ok(Functions {
+                    $($name: *lib.get(stringify!($name).as_bytes())
+                        .map_err(|_| stringify!($name))?,)*
+                })
+            }
+        }
+
+        $(
+            #[cfg(feature = "dynamic")]
+            pub(crate) unsafe fn $name($($arg: $ty),*) -> $ret {
+                (loaded().functions.$name)($($arg),*)
+            }
+        )*
+    };
+}
+
+functions! {
+    fn rtlsdr_get_device_count() -> u32;
+    fn rtlsdr_get_device_name(index: u32) -> *const c_char;
+    fn rtlsdr_get_device_usb_strings(index: u32,
+                                     manufact: *mut c_char,
+                                     product: *mut c_char,
+                                     serial: *mut c_char)
+                                     -> c_int;
+    fn rtlsdr_get_index_by_serial(serial: *const c_char) -> c_int;
+
+    fn rtlsdr_open(dev: *mut *mut RTLSDRDevT, index: u32) -> c_int;
+    fn rtlsdr_close(dev: *mut RTLSDRDevT) -> c_int;
+    fn rtlsdr_set_xtal_freq(dev: *mut RTLSDRDevT, rtl_freq: u32, tuner_freq: u32) -> c_int;
+    fn rtlsdr_get_xtal_freq(dev: *mut RTLSDRDevT,
+                            rtl_freq: *mut u32,
+                            tuner_freq: *mut u32)
+                            -> c_int;
+    fn rtlsdr_get_usb_strings(dev: *mut RTLSDRDevT,
+                              manufact: *mut c_char,
+                              product: *mut c_char,
+                              serial: *mut c_char)
+                              -> c_int;
+    fn rtlsdr_write_eeprom(dev: *mut RTLSDRDevT, data: *mut u8, offset: u8, len: u16) -> c_int;
+    fn rtlsdr_read_eeprom(dev: *mut RTLSDRDevT, data: *mut u8, offset: u8, len: u16) -> c_int;
+    fn rtlsdr_set_center_freq(dev: *mut RTLSDRDevT, freq: u32) -> c_int;
+    fn rtlsdr_get_center_freq(dev: *mut RTLSDRDevT) -> u32;
+    fn rtlsdr_set_freq_correction(dev: *mut RTLSDRDevT, ppm: c_int) -> c_int;
+    fn rtlsdr_get_freq_correction(dev: *mut RTLSDRDevT) -> c_int;
+    fn rtlsdr_get_tuner_type(dev: *mut RTLSDRDevT) -> c_int;
+    fn rtlsdr_get_tuner_gains(dev: *mut RTLSDRDevT, gains: *mut c_int) -> c_int;
+    fn rtlsdr_set_tuner_gain(dev: *mut RTLSDRDevT, gain: c_int) -> c_int;
+    fn rtlsdr_set_tuner_bandwidth(dev: *mut RTLSDRDevT, bw: u32) -> c_int;
+    fn rtlsdr_get_tuner_gain(dev: *mut RTLSDRDevT) -> c_int;
+    fn rtlsdr_set_tuner_if_gain(dev: *mut RTLSDRDevT, stage: c_int, gain: c_int) -> c_int;
+    fn rtlsdr_set_tuner_gain_mode(dev: *mut RTLSDRDevT, manual: c_int) -> c_int;
+    fn rtlsdr_set_sample_rate(dev: *mut RTLSDRDevT, rate: u32) -> c_int;
+    fn rtlsdr_get_sample_rate(dev: *mut RTLSDRDevT) -> u32;
+    fn rtlsdr_set_testmode(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
+    fn rtlsdr_set_agc_mode(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
+    fn rtlsdr_set_direct_sampling(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
+    fn rtlsdr_get_direct_sampling(dev: *mut RTLSDRDevT) -> c_int;
+    fn rtlsdr_set_offset_tuning(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
+    fn rtlsdr_get_offset_tuning(dev: *mut RTLSDRDevT) -> c_int;
+    fn rtlsdr_reset_buffer(dev: *mut RTLSDRDevT) -> c_int;
+    fn rtlsdr_read_sync(dev: *mut RTLSDRDevT,
+                        buf: *mut c_void,
+                        len: c_int,
+                        n_read: *mut c_int)
+                        -> c_int;
+    fn rtlsdr_wait_async(dev: *mut RTLSDRDevT, cb: ReadAsyncCbT, ctx: *mut c_void) -> c_int;
+    fn rtlsdr_read_async(dev: *mut RTLSDRDevT,
+                         cb: ReadAsyncCbT,
+                         ctx: *mut c_void,
+                         buf_num: u32,
+                         buf_len: u32)
+                         -> c_int;
+    fn rtlsdr_cancel_async(dev: *mut RTLSDRDevT) -> c_int;
+}
+
+// Signatures of the optional functions the crate calls.
+pub(crate) type SetDitheringFn = unsafe extern "C" fn(*mut RTLSDRDevT, c_int) -> c_int;
+pub(crate) type SetBiasTeeFn = unsafe extern "C" fn(*mut RTLSDRDevT, c_int) -> c_int;
+pub(crate) type SetBiasTeeGpioFn = unsafe extern "C" fn(*mut RTLSDRDevT, c_int, c_int) -> c_int;
+
+fn not_supported(op: &'static str) -> Error {
+    Error::with_kind(op, ErrorKind::NotSupported, -12)
+}
+
+#[cfg(feature = "dynamic")]
+struct Loaded {
+    lib: Library,
+    path: PathBuf,
+    functions: Functions,
+}
+
+#[cfg(feature = "dynamic")]
+static LOADED: OnceLock<Loaded> = OnceLock::new();
+
+// Serializes loading, OnceLock can't fail to initialize.
+#[cfg(feature = "dynamic")]
+static LOADING: Mutex<()> = Mutex::new(());
+
+// Only called once a device or index function made sure it's loaded.
+#[cfg(feature = "dynamic")]
+fn loaded() -> &'static Loaded {
+    LOADED.get().expect("librtlsdr called before it was loaded")
+}
+
+#[cfg(feature = "dynamic")]
+fn default_names() -> Vec<OsString> {
+    let names: &[&str] = if cfg!(windows) {
+        &["rtlsdr.dll", "librtlsdr.dll"]
+    } else if cfg!(target_os = "macos") {
+        &["librtlsdr.0.dylib", "librtlsdr.dylib"]
+    } else {
+        &["librtlsdr.so.0", "librtlsdr.so"]
+    };
+    match env::var_os("RTLSDR_LIBRARY") {
+        Some(path) => vec![path],
+        None => names.iter().map(OsString::from).collect(),
+    }
+}
+
+#[cfg(feature = "dynamic")]
+fn load_from(op: &'static str, path: &Path) -> Result<()> {
+    let _guard = LOADING.lock().unwrap_or_else(|e| e.into_inner());
+    if LOADED.get().is_some() {
+        return Err(Error::with_kind(op, ErrorKind::Busy, -6));
+    }
+    let lib = unsafe { Library::new(path) }
+        .map_err(|_| Error::with_kind(op, ErrorKind::NotFound, -5))?;
+    let functions = unsafe { Functions::load(&lib) }.map_err(not_supported)?;
+    let _ = LOADED.set(Loaded {
+        lib,
+        path: path.to_path_buf(),
+        functions,
+    });
+    Ok(())
+}
+
+/// Makes sure librtlsdr is loaded, loading it by its usual names if not.
+#[cfg(feature = "dynamic")]
+pub(crate) fn load() -> Result<()> {
+    if LOADED.get().is_some() {
+        return Ok(());
+    }
+    let mut err = Error::with_kind("load_library", ErrorKind::NotFound, -5);
+    for name in default_names() {
+        match load_from("load_library", Path::new(&name)) {
+            // Another thread got there first.
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::Busy => return Ok(()),
+            Err(e) => err = e,
+        }
+    }
+    Err(err)
+}
+
+/// Makes sure librtlsdr is loaded, it always is when linked.
+#[cfg(not(feature = "dynamic"))]
+pub(crate) fn load() -> Result<()> {
+    Ok(())
+}
+
+/// Loads librtlsdr from `path` instead of searching for it, before any
+/// other call into it.
+///
+/// Fails with `NotFound` if the library can't be opened, `NotSupported` if
+/// it lacks one of the functions every librtlsdr has and `Busy` if a
+/// library has already been loaded.
+#[cfg(feature = "dynamic")]
+pub fn load_library<P: AsRef<Path>>(path: P) -> Result<LibraryInfo> {
+    load_from("load_library", path.as_ref())?;
+    library_info()
+}
+
+#[cfg(any(unix, feature = "dynamic"))]
+fn lookup<T: Copy>(lib: &Library, name: &'static str) -> Result<T> {
+    unsafe { lib.get::<T>(name.as_bytes()) }.map(|f| *f).map_err(|_| not_supported(name))
+}
+
+/// Looks up the optional function `name`, failing with `NotSupported` if
+/// the library doesn't have it.
+#[cfg(feature = "dynamic")]
+pub(crate) fn optional<T: Copy>(name: &'static str) -> Result<T> {
+    load()?;
+    lookup(&loaded().lib, name)
+}
+
+/// Looks up the optional function `name` in the running process, failing
+/// with `NotSupported` if the linked library doesn't have it.
+#[cfg(all(unix, not(feature = "dynamic")))]
+pub(crate) fn optional<T: Copy>(name: &'static str) -> Result<T> {
+    // librtlsdr stays loaded, closing this handle leaves the symbol valid.
+    lookup(&libloading::os::unix::Library::this().into(), name)
+}
+
+/// Looks up the optional function `name`, always failing with
+/// `NotSupported`, only unix can look symbols up in the running process.
+#[cfg(all(not(unix), not(feature = "dynamic")))]
+pub(crate) fn optional<T: Copy>(name: &'static str) -> Result<T> {
+    Err(not_supported(name))
+}
+
+fn optional_functions() -> Vec<&'static str> {
+    OPTIONAL_FUNCTIONS.iter()
+        .cloned()
+        .filter(|name| optional::<*const c_void>(name).is_ok())
+        .collect()
+}
+
+// The version suffix of a library file name, librtlsdr.so.0.6.0 or
+// librtlsdr.0.6.0.dylib.
+#[cfg(feature = "dynamic")]
+fn file_version(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let version = match name.find(".so.") {
+        Some(i) => &name[i + 4..],
+        None => name.strip_prefix("librtlsdr.")?.strip_suffix(".dylib")?,
+    };
+    if version.is_empty() {
+        None
+    } else {
+        Some(version.to_string())
+    }
+}
+
+/// Reports which librtlsdr is in use and which `OPTIONAL_FUNCTIONS` it has,
+/// loading it if it's loaded at runtime and not yet loaded.
+#[cfg(feature = "dynamic")]
+pub fn library_info() -> Result<LibraryInfo> {
+    load()?;
+    let loaded = loaded();
+    // Follow symlinks such as librtlsdr.so.0 -> librtlsdr.so.0.6.0.
+    let version = fs::canonicalize(&loaded.path)
+        .ok()
+        .and_then(|p| file_version(&p))
+        .or_else(|| file_version(&loaded.path));
+    Ok(LibraryInfo {
+        dynamic: true,
+        path: Some(loaded.path.clone()),
+        version,
+        functions: optional_functions(),
+    })
+}
+
+/// Reports which librtlsdr is in use and which `OPTIONAL_FUNCTIONS` it has,
+/// loading it if it's loaded at runtime and not yet loaded.
+#[cfg(not(feature = "dynamic"))]
+pub fn library_info() -> Result<LibraryInfo> {
+    Ok(LibraryInfo {
+        dynamic: false,
+        path: None,
+        version: None,
+        functions: optional_functions(),
+    })
+}
@@ -8,23 +8,66 @@
 // except according to those terms.
 
 #![allow(dead_code)]
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::cell::Cell;
 use std::os::raw::{c_int, c_void, c_uchar, c_char};
 use std::option::Option;
 use std::string::String;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
+use std::any::Any;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
-use std::str;
-
+use std::slice;
+
+mod config;
+mod enumerate;
+mod error;
+mod ffi;
+mod resampler;
+mod source;
+mod stream;
+mod tuner;
+mod units;
+#[cfg(feature = "async")]
+mod async_device;
+pub mod compat;
+pub mod eeprom;
+pub mod replay;
+pub mod serial;
+pub mod sim;
+pub mod tcp;
+
+pub use config::{ApplyError, DeviceConfig};
+pub use eeprom::EepromImage;
+pub use enumerate::{devices, open_by_product_match, open_by_serial, probe_devices, usb_ids,
+                    DeviceInfo};
+#[cfg(target_os = "linux")]
+pub use enumerate::open_by_usb_path;
+pub use error::{Error, ErrorKind, Result};
+#[cfg(feature = "dynamic")]
+pub use ffi::load_library;
+pub use ffi::{library_info, LibraryInfo, OPTIONAL_FUNCTIONS};
+pub use resampler::{check_sample_rate, exact_sample_rate, nearest_exact_sample_rate};
+pub use source::SdrSource;
+pub use stream::{Buffer, Overflow, Stream, StreamOptions};
+pub use tuner::{Capabilities, Gain, TunerType};
+pub use units::{Hertz, Ppm, SampleRate, TenthDb};
+#[cfg(feature = "async")]
+pub use async_device::{AsyncDevice, SampleStream};
+use error::{check, ERROR_UNKNOWN};
+use ffi::*;
+use resampler::nominal_xtal;
 
 // TODO:
 // - better function/method documnentation
-// - better way to handle errors
 // - String vs str
 // - tests
 // - read more Rust code, learn more Rust, make this lib better
 
 /// Sampling modes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SamplingMode {
     None = 0,
     IADC = 1,
@@ -40,295 +83,253 @@ pub const DEFAULT_READ_SIZE: i32 = 1_024;
 pub const CRYSTAL_FREQ: i32 = 28_800_000;
 pub const DEFAULT_SAMPLE_RATE: i32 = 2_048_000;
 pub const DEFAULT_ASYNC_BUF_NUMBER: i32 = 32;
-pub const DEFAULT_BUF_LENGTH: i32 = (16 * 16_384);
+pub const DEFAULT_BUF_LENGTH: i32 = 16 * 16_384;
 pub const MIN_BUF_LENGTH: i32 = 512;
-pub const MAX_BUF_LENGTH: i32 = (256 * 16_384);
+pub const MAX_BUF_LENGTH: i32 = 256 * 16_384;
 /// Hardware info strings (product, manufacturer, serial) maximum size.
 /// MAX_STR_SIZE = (max string length - 2 (header bytes)) \ 2. Where each
 /// info character is followed by a null character.
 pub const MAX_STR_SIZE: usize = 35;
 
-// EEPROM stored device information starts after the header bytes.
-const STR_OFFSET_START: usize = 0x09;
 const EEPROM_SIZE: i32 = 256;
-/// Get/set hardware info errors.
-const NO_VALID_EEPROM_HEADER: i32 = -13;
-const STRING_VALUE_TOO_LONG: i32 = -14;
-const STRING_DESCRIPTOR_INVALID: i32 = -15;
-const STRING__DESCRIPTOR_TOO_LONG: i32 = -16;
 
-//
-const ERROR_ONKNOWN: i32 = -98;
-const LIBUSB_ERROR_ONKNOWN: i32 = -99;
+/// The highest RTL2832 GPIO number, see `Device::set_bias_tee_gpio`.
+pub const MAX_GPIO: u8 = 7;
 
 // C lib opaque device struct
 enum RTLSDRDev { }
 type RTLSDRDevT = RTLSDRDev;
 
-#[derive(Copy, Clone)]
+/// An opened device, closed exactly once when dropped.
+///
+/// The handle is `Send` but not `Sync`, librtlsdr isn't safe to drive from
+/// several threads at once. Use a `Canceller` to stop `read_async` from
+/// another thread.
 pub struct Device {
     dev: *mut RTLSDRDevT,
+    shared: Arc<Mutex<DevPtr>>,
+    // The rate librtlsdr last derived the resampler ratio from, the one it
+    // reports is truncated.
+    rate: Cell<u32>,
+    // librtlsdr has no getter for the gain mode.
+    manual_gain: Cell<bool>,
+    // The GPIOs with a bias tee switched on, one bit each, switched off on
+    // close. librtlsdr has no getter for them either.
+    bias_tees: Cell<u8>,
 }
 
 unsafe impl Send for Device {}
-unsafe impl Sync for Device {}
 
-// HwInfo holds dongle specific information.
-#[derive(Debug)]
+// Device pointer shared with cancellers, nulled before the device closes.
+struct DevPtr(*mut RTLSDRDevT);
+
+unsafe impl Send for DevPtr {}
+
+/// A cloneable, thread-safe handle for cancelling a device's pending
+/// asynchronous reads.
+///
+/// Cancelling after the device has been closed returns a `NoDevice` error.
+#[derive(Clone)]
+pub struct Canceller {
+    cancel: Arc<dyn Fn() -> Result<()> + Send + Sync>,
+}
+
+/// HwInfo holds dongle specific information.
+///
+/// With the `serde` feature it serializes with the field names below.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HwInfo {
+    /// USB vendor ID, e.g. 0x0bda for Realtek.
     pub vendor_id: u16,
+    /// USB product ID, e.g. 0x2838.
     pub product_id: u16,
+    /// Manufacturer string.
     pub manufact: String,
+    /// Product string.
     pub product: String,
+    /// Serial number string.
     pub serial: String,
+    /// Whether the serial number is reported over USB.
     pub have_serial: bool,
+    /// Whether the IR endpoint is enabled.
     pub enable_ir: bool,
+    /// Whether remote wakeup is enabled.
     pub remote_wakeup: bool,
 }
 
-#[derive(Copy, Clone)]
-#[repr(u32)]
-#[derive(Debug)]
-enum RTLSDRTuner {
-    Unknown = 0,
-    E4000 = 1,
-    FC0012 = 2,
-    FC0013 = 3,
-    FC2580 = 4,
-    R820T = 5,
-    R828D = 6,
-}
-
-#[derive(Copy, Clone)]
-#[derive(Debug)]
-pub enum Error {
-    NoError,
-    Io,
-    InvalidParam,
-    Access,
-    NoDevice,
-    NotFound,
-    Busy,
-    Timeout,
-    Overflow,
-    Pipe,
-    Interrupted,
-    NoMem,
-    NotSupported,
-    NoValidEEPROMHeader,
-    StringValueTooLong,
-    StringDescriptorInvalid,
-    StringDescriptorTooLong,
-    Unknown,
-}
-
-
-
 /// read async callback function
 pub type ReadAsyncCbT = Option<unsafe extern "C" fn(buf: *mut c_uchar, len: u32, ctx: *mut c_void)>;
 
-#[link(name = "rtlsdr")]
-extern "C" {
-    fn rtlsdr_get_device_count() -> u32;
-    fn rtlsdr_get_device_name(index: u32) -> *const c_char;
-    fn rtlsdr_get_device_usb_strings(index: u32,
-                                     manufact: *mut c_char,
-                                     product: *mut c_char,
-                                     serial: *mut c_char)
-                                     -> c_int;
-    fn rtlsdr_get_index_by_serial(serial: *const c_char) -> c_int;
-
-    fn rtlsdr_open(dev: *mut *mut RTLSDRDevT, index: u32) -> c_int;
-    fn rtlsdr_close(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_set_xtal_freq(dev: *mut RTLSDRDevT, rtl_freq: u32, tuner_freq: u32) -> c_int;
-    fn rtlsdr_get_xtal_freq(dev: *mut RTLSDRDevT,
-                            rtl_freq: *mut u32,
-                            tuner_freq: *mut u32)
-                            -> c_int;
-    fn rtlsdr_get_usb_strings(dev: *mut RTLSDRDevT,
-                              manufact: *mut c_char,
-                              product: *mut c_char,
-                              serial: *mut c_char)
-                              -> c_int;
-    fn rtlsdr_write_eeprom(dev: *mut RTLSDRDevT, data: *mut u8, offset: u8, len: u16) -> c_int;
-    fn rtlsdr_read_eeprom(dev: *mut RTLSDRDevT, data: *mut u8, offset: u8, len: u16) -> c_int;
-    fn rtlsdr_set_center_freq(dev: *mut RTLSDRDevT, freq: u32) -> c_int;
-    fn rtlsdr_get_center_freq(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_set_freq_correction(dev: *mut RTLSDRDevT, ppm: c_int) -> c_int;
-    fn rtlsdr_get_freq_correction(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_get_tuner_type(dev: *mut RTLSDRDevT) -> RTLSDRTuner;
-    fn rtlsdr_get_tuner_gains(dev: *mut RTLSDRDevT, gains: *mut c_int) -> c_int;
-    fn rtlsdr_set_tuner_gain(dev: *mut RTLSDRDevT, gain: c_int) -> c_int;
-    fn rtlsdr_set_tuner_bandwidth(dev: *mut RTLSDRDevT, bw: u32) -> c_int;
-    fn rtlsdr_get_tuner_gain(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_set_tuner_if_gain(dev: *mut RTLSDRDevT, stage: c_int, gain: c_int) -> c_int;
-    fn rtlsdr_set_tuner_gain_mode(dev: *mut RTLSDRDevT, manual: c_int) -> c_int;
-    fn rtlsdr_set_sample_rate(dev: *mut RTLSDRDevT, rate: u32) -> c_int;
-    fn rtlsdr_get_sample_rate(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_set_testmode(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
-    fn rtlsdr_set_agc_mode(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
-    fn rtlsdr_set_direct_sampling(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
-    fn rtlsdr_get_direct_sampling(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_set_offset_tuning(dev: *mut RTLSDRDevT, on: c_int) -> c_int;
-    fn rtlsdr_get_offset_tuning(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_reset_buffer(dev: *mut RTLSDRDevT) -> c_int;
-    fn rtlsdr_read_sync(dev: *mut RTLSDRDevT,
-                        buf: *mut c_void,
-                        len: c_int,
-                        n_read: *mut c_int)
-                        -> c_int;
-    fn rtlsdr_wait_async(dev: *mut RTLSDRDevT, cb: ReadAsyncCbT, ctx: *mut c_void) -> c_int;
-    fn rtlsdr_read_async(dev: *mut RTLSDRDevT,
-                         cb: ReadAsyncCbT,
-                         ctx: *mut c_void,
-                         buf_num: u32,
-                         buf_len: u32)
-                         -> c_int;
-    fn rtlsdr_cancel_async(dev: *mut RTLSDRDevT) -> c_int;
+/// Tells read_async_until whether to keep reading.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AsyncControl {
+    Continue,
+    Stop,
 }
 
-// FIXME: there has to be a better way...
-fn get_err_msg(e: c_int) -> Error {
-    match e {
-        0 => Error::NoError,
-        -1 => Error::Io,
-        -2 => Error::InvalidParam,
-        -3 => Error::Access,
-        -4 => Error::NoDevice,
-        -5 => Error::NotFound,
-        -6 => Error::Busy,
-        -7 => Error::Timeout,
-        -8 => Error::Overflow,
-        -9 => Error::Pipe,
-        -10 => Error::Interrupted,
-        -11 => Error::NoMem,
-        -12 => Error::NotSupported,
-        NO_VALID_EEPROM_HEADER => Error::NoValidEEPROMHeader,
-        STRING_VALUE_TOO_LONG => Error::StringValueTooLong,
-        STRING_DESCRIPTOR_INVALID => Error::StringDescriptorInvalid,
-        STRING__DESCRIPTOR_TOO_LONG => Error::StringDescriptorTooLong,
-        _ => Error::Unknown,
-    }
+// State handed to read_async_trampoline through librtlsdr's ctx pointer.
+struct AsyncCtx<'a> {
+    f: &'a mut dyn FnMut(&[u8]) -> AsyncControl,
+    dev: *mut RTLSDRDevT,
+    stopped: bool,
+    panic: Option<Box<dyn Any + Send>>,
 }
 
-fn from_tuner_type(t: RTLSDRTuner) -> String {
-    match t {
-        RTLSDRTuner::Unknown => String::from("Unknown"),
-        RTLSDRTuner::E4000 => String::from("E4000"),
-        RTLSDRTuner::FC0012 => String::from("FC0012"),
-        RTLSDRTuner::FC0013 => String::from("FC0013"),
-        RTLSDRTuner::FC2580 => String::from("FC2580"),
-        RTLSDRTuner::R820T => String::from("R820T"),
-        RTLSDRTuner::R828D => String::from("R828D"),
+unsafe extern "C" fn read_async_trampoline(buf: *mut c_uchar, len: u32, ctx: *mut c_void) {
+    let ctx = &mut *(ctx as *mut AsyncCtx);
+    // libusb may still deliver in-flight transfers after a cancel.
+    if ctx.stopped {
+        return;
+    }
+    let data: &[u8] = if buf.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(buf, len as usize)
+    };
+    let f = &mut ctx.f;
+    let stop = match panic::catch_unwind(AssertUnwindSafe(|| f(data))) {
+        Ok(ctl) => ctl == AsyncControl::Stop,
+        Err(p) => {
+            ctx.panic = Some(p);
+            true
+        }
+    };
+    if stop {
+        ctx.stopped = true;
+        rtlsdr_cancel_async(ctx.dev);
     }
 }
 
 fn from_pchar(p: *const c_char) -> String {
-    let c_str = unsafe { CStr::from_ptr(p) };
-    String::from(str::from_utf8(c_str.to_bytes()).unwrap())
+    unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
 }
 
 /// Returns the number of devices detected.
 pub fn get_device_count() -> i32 {
+    if ffi::load().is_err() {
+        return 0;
+    }
     unsafe { rtlsdr_get_device_count() as i32 }
 }
 
 /// Returns the name of the device by index.
 pub fn get_device_name(index: i32) -> String {
+    if ffi::load().is_err() {
+        return String::new();
+    }
     unsafe { CStr::from_ptr(rtlsdr_get_device_name(index as u32)).to_string_lossy().into_owned() }
 }
 
-/// Returns the information of a device by index.
-pub fn get_device_usb_strings(index: i32) -> (String, String, String, Error) {
+/// Returns the information (manufact, product, serial) of a device by index.
+pub fn get_device_usb_strings(index: i32) -> Result<(String, String, String)> {
+    let m: [c_char; 256] = [0; 256];
+    let p: [c_char; 256] = [0; 256];
+    let s: [c_char; 256] = [0; 256];
+    ffi::load()?;
     unsafe {
-        let m: [c_char; 256] = [0; 256];
-        let p: [c_char; 256] = [0; 256];
-        let s: [c_char; 256] = [0; 256];
-        let err = rtlsdr_get_device_usb_strings(index as u32,
-                                                m.as_ptr() as *mut c_char,
-                                                p.as_ptr() as *mut c_char,
-                                                s.as_ptr() as *mut c_char);
-        (from_pchar(m.as_ptr()), from_pchar(p.as_ptr()), from_pchar(s.as_ptr()), get_err_msg(err))
+        check("rtlsdr_get_device_usb_strings",
+              rtlsdr_get_device_usb_strings(index as u32,
+                                            m.as_ptr() as *mut c_char,
+                                            p.as_ptr() as *mut c_char,
+                                            s.as_ptr() as *mut c_char))?;
     }
+    Ok((from_pchar(m.as_ptr()), from_pchar(p.as_ptr()), from_pchar(s.as_ptr())))
 }
 
 /// Returns a device index by serial id.
-pub fn get_index_by_serial(serial: String) -> i32 {
-    unsafe { rtlsdr_get_index_by_serial(serial.as_ptr() as *const c_char) as i32 }
+pub fn get_index_by_serial(serial: String) -> Result<i32> {
+    let op = "rtlsdr_get_index_by_serial";
+    // librtlsdr takes a NUL terminated string, reject embedded NULs.
+    let serial = CString::new(serial)
+        .map_err(|_| Error::with_kind(op, ErrorKind::InvalidParam, -1))?;
+    ffi::load()?;
+    match unsafe { rtlsdr_get_index_by_serial(serial.as_ptr()) } {
+        i if i >= 0 => Ok(i),
+        -2 => Err(Error::with_kind(op, ErrorKind::NoDevice, -2)),
+        -3 => Err(Error::with_kind(op, ErrorKind::NotFound, -3)),
+        e => Err(Error::with_kind(op, ErrorKind::InvalidParam, e)),
+    }
 }
 
 /// Returns an opened device by index.
-pub fn open(index: i32) -> (Arc<Device>, Error) {
+pub fn open(index: i32) -> Result<Device> {
+    ffi::load()?;
+    let mut dev: *mut RTLSDRDevT = ptr::null_mut();
     unsafe {
-        let mut dev: *mut RTLSDRDevT = std::ptr::null_mut();
-        let err = rtlsdr_open(&mut dev as *mut *mut RTLSDRDevT, index as u32);
-        (Arc::new(Device { dev: dev }), get_err_msg(err))
-    }
+        check("rtlsdr_open", rtlsdr_open(&mut dev as *mut *mut RTLSDRDevT, index as u32))?;
+    }
+    if dev.is_null() {
+        return Err(Error::with_kind("rtlsdr_open", ErrorKind::NoDevice, 0));
+    }
+    Ok(Device {
+        dev,
+        shared: Arc::new(Mutex::new(DevPtr(dev))),
+        rate: Cell::new(0),
+        manual_gain: Cell::new(false),
+        bias_tees: Cell::new(0),
+    })
 }
 
-/// Gets the manufacturer, product, and serial strings from data.
-fn get_string_descriptors(data: &Vec<u8>) -> (String, String, String, Error) {
-    let mut pos = STR_OFFSET_START;
-    let mut strings: Vec<String> = Vec::new();
-
-    for _ in 0..3 {
-        let l = data[pos] as usize;
-        if l > (MAX_STR_SIZE * 2) as usize + 2 {
-            return ("".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    get_err_msg(STRING_VALUE_TOO_LONG));
-        }
-        if data[pos + 1] != 0x03 {
-            return ("".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    get_err_msg(STRING_DESCRIPTOR_INVALID));
-        }
-
-        let mut j: usize = 2;
-        let mut s = String::new();
-        while j < l {
-            s.push(data[pos + j] as char);
-            j += 2;
-        }
-        strings.push(s);
-        pos += j;
+impl Drop for Device {
+    fn drop(&mut self) {
+        let _ = self.close_dev();
     }
-    (strings[0].clone(), strings[1].clone(), strings[2].clone(), get_err_msg(0))
 }
 
-/// Sets the manufacturer, product, and serial strings in vec format.
-fn set_string_descriptors(info: &HwInfo, data: &mut Vec<u8>) -> Error {
-    let mlen = info.manufact.len();
-    let plen = info.product.len();
-    let slen = info.serial.len();
-
-    if mlen > MAX_STR_SIZE || plen > MAX_STR_SIZE || slen > MAX_STR_SIZE {
-        return get_err_msg(STRING__DESCRIPTOR_TOO_LONG);
+impl Canceller {
+    /// Creates a canceller from the function that cancels a source's
+    /// asynchronous reads, for `SdrSource` implementations.
+    pub fn new<F>(cancel: F) -> Canceller
+        where F: Fn() -> Result<()> + Send + Sync + 'static
+    {
+        Canceller { cancel: Arc::new(cancel) }
     }
 
-    let mut pos = STR_OFFSET_START;
-    let strings = [&info.manufact, &info.product, &info.serial];
-    for s in strings.iter() {
-        data[pos] = ((s.len() * 2) + 2) as u8;
-        data[pos + 1] = 0x03u8;
-        pos += 2;
-
-        for b in s.as_bytes().iter() {
-            data[pos] = *b;
-            data[pos + 1] = 0x00u8;
-            pos += 2;
-        }
+    /// Cancels all pending asynchronous operations.
+    pub fn cancel_async(&self) -> Result<()> {
+        (self.cancel)()
     }
-
-    get_err_msg(0)
 }
 
 impl Device {
     /// Close the device.
-    pub fn close(&self) -> Error {
-        unsafe { get_err_msg(rtlsdr_close(self.dev)) }
+    ///
+    /// Dropping the device closes it as well, this only surfaces the error.
+    pub fn close(mut self) -> Result<()> {
+        self.close_dev()
+    }
+
+    fn close_dev(&mut self) -> Result<()> {
+        if self.dev.is_null() {
+            return Ok(());
+        }
+        // A bias tee left on keeps powering the antenna, switch them off
+        // but close the device even if that fails.
+        let mut off = Ok(());
+        for gpio in 0..=MAX_GPIO {
+            if self.get_bias_tee_gpio(gpio) {
+                off = off.and(self.set_bias_tee_gpio(gpio, false));
+            }
+        }
+        // Keep cancellers off the pointer before it's freed.
+        self.shared.lock().unwrap_or_else(|e| e.into_inner()).0 = ptr::null_mut();
+        let dev = mem::replace(&mut self.dev, ptr::null_mut());
+        unsafe { check("rtlsdr_close", rtlsdr_close(dev))? };
+        off
+    }
+
+    /// Returns a handle that can cancel this device's asynchronous reads
+    /// from another thread.
+    pub fn canceller(&self) -> Canceller {
+        let shared = self.shared.clone();
+        Canceller::new(move || {
+            let dev = shared.lock().unwrap_or_else(|e| e.into_inner());
+            if dev.0.is_null() {
+                return Err(Error::with_kind("rtlsdr_cancel_async", ErrorKind::NoDevice, 0));
+            }
+            unsafe { check("rtlsdr_cancel_async", rtlsdr_cancel_async(dev.0)).map(|_| ()) }
+        })
     }
 
     /// Sets the crystal oscillator frequencies.
@@ -339,114 +340,126 @@ impl Device {
     /// original (cheap) crystal.
     ///
     /// Note, call this function only if you fully understand the implications.
-    pub fn set_xtal_freq(&self, rtl_freq_hz: i32, tuner_freq_hz: i32) -> Error {
+    pub fn set_xtal_freq(&self, rtl_freq: Hertz, tuner_freq: Hertz) -> Result<()> {
+        let ppm = self.get_freq_correction();
+        let xtal = self.get_xtal_freq().map(|(rtl, _)| nominal_xtal(rtl, ppm));
+        let rate = self.get_sample_rate();
         unsafe {
-            get_err_msg(rtlsdr_set_xtal_freq(self.dev, rtl_freq_hz as u32, tuner_freq_hz as u32))
+            check("rtlsdr_set_xtal_freq",
+                  rtlsdr_set_xtal_freq(self.dev, rtl_freq.hz(), tuner_freq.hz()))?;
+        }
+        // A new RTL2832 clock re-applies the truncated sample rate.
+        if rtl_freq.hz() > 0 && xtal.ok() != Some(rtl_freq) && self.rate.get() != 0 {
+            self.rate.set(rate.sps());
         }
+        Ok(())
     }
 
-    /// Returns the crystal oscillator frequencies.
+    /// Returns the crystal oscillator frequencies (rtl_freq, tuner_freq).
     /// Typically both ICs (rtlsdr and tuner) use the same clock.
-    pub fn get_xtal_freq(&self) -> (i32, i32, Error) {
+    pub fn get_xtal_freq(&self) -> Result<(Hertz, Hertz)> {
         let mut rtl_freq_hz: u32 = 0;
         let mut tuner_freq_hz: u32 = 0;
         unsafe {
-            let err = rtlsdr_get_xtal_freq(self.dev,
-                                           &mut rtl_freq_hz as *mut u32,
-                                           &mut tuner_freq_hz as *mut u32);
-            (rtl_freq_hz as i32, tuner_freq_hz as i32, get_err_msg(err))
+            check("rtlsdr_get_xtal_freq",
+                  rtlsdr_get_xtal_freq(self.dev,
+                                       &mut rtl_freq_hz as *mut u32,
+                                       &mut tuner_freq_hz as *mut u32))?;
         }
+        Ok((Hertz::new(rtl_freq_hz), Hertz::new(tuner_freq_hz)))
     }
 
     /// Returns the device information (manufact, product, serial).
     /// Note, strings may be empty.
-    pub fn get_usb_strings(&self) -> (String, String, String, Error) {
+    pub fn get_usb_strings(&self) -> Result<(String, String, String)> {
         let m: [c_char; 256] = [0; 256];
         let p: [c_char; 256] = [0; 256];
         let s: [c_char; 256] = [0; 256];
         unsafe {
-            let err = rtlsdr_get_usb_strings(self.dev,
-                                             m.as_ptr() as *mut c_char,
-                                             p.as_ptr() as *mut c_char,
-                                             s.as_ptr() as *mut c_char);
-            (from_pchar(m.as_ptr()),
-             from_pchar(p.as_ptr()),
-             from_pchar(s.as_ptr()),
-             get_err_msg(err))
+            check("rtlsdr_get_usb_strings",
+                  rtlsdr_get_usb_strings(self.dev,
+                                         m.as_ptr() as *mut c_char,
+                                         p.as_ptr() as *mut c_char,
+                                         s.as_ptr() as *mut c_char))?;
         }
+        Ok((from_pchar(m.as_ptr()), from_pchar(p.as_ptr()), from_pchar(s.as_ptr())))
     }
 
     /// Writes information data to the EEPROM.
-    pub fn write_eeprom(&self, data: Vec<u8>, offset: u8) -> Error {
+    pub fn write_eeprom(&self, data: Vec<u8>, offset: u8) -> Result<()> {
         unsafe {
-            let mut err = rtlsdr_write_eeprom(self.dev,
-                                              data.as_ptr() as *mut u8,
-                                              offset,
-                                              data.len() as u16);
-            if err >= 0 {
-                err = 0;
-            }
-            get_err_msg(err)
+            check("rtlsdr_write_eeprom",
+                  rtlsdr_write_eeprom(self.dev,
+                                      data.as_ptr() as *mut u8,
+                                      offset,
+                                      data.len() as u16))
+                .map(|_| ())
         }
     }
 
     /// Returns information data read from the EEPROM.
-    pub fn read_eeprom(&self, offset: u8, len: u16) -> (Vec<u8>, Error) {
+    pub fn read_eeprom(&self, offset: u8, len: u16) -> Result<Vec<u8>> {
         let mut v = vec![0u8; len as usize];
         unsafe {
-            let mut err = rtlsdr_read_eeprom(self.dev, v.as_mut_ptr() as *mut u8, offset, len);
-            if err >= 0 {
-                err = 0;
-            }
-            (v, get_err_msg(err))
+            check("rtlsdr_read_eeprom",
+                  rtlsdr_read_eeprom(self.dev, v.as_mut_ptr(), offset, len))?;
         }
-
+        Ok(v)
     }
 
     /// Sets the center frequency.
-    pub fn set_center_freq(&self, freq_hz: i32) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_center_freq(self.dev, freq_hz as u32)) }
+    pub fn set_center_freq(&self, freq: Hertz) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_center_freq",
+                  rtlsdr_set_center_freq(self.dev, freq.hz()))
+                .map(|_| ())
+        }
     }
 
     /// Returns the tuned frequency or zero on error.
-    pub fn get_center_freq(&self) -> i32 {
-        unsafe { rtlsdr_get_center_freq(self.dev) as i32 }
+    pub fn get_center_freq(&self) -> Hertz {
+        unsafe { Hertz::new(rtlsdr_get_center_freq(self.dev)) }
     }
 
     /// Sets the frequency correction.
-    pub fn set_freq_correction(&self, ppm: i32) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_freq_correction(self.dev, ppm)) }
+    pub fn set_freq_correction(&self, ppm: Ppm) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_freq_correction",
+                  rtlsdr_set_freq_correction(self.dev, ppm.ppm()))
+                .map(|_| ())
+        }
     }
 
     /// Returns the frequency correction value.
-    pub fn get_freq_correction(&self) -> i32 {
-        unsafe { rtlsdr_get_freq_correction(self.dev) }
+    pub fn get_freq_correction(&self) -> Ppm {
+        unsafe { Ppm::new(rtlsdr_get_freq_correction(self.dev)) }
     }
 
     /// Returns the tuner type.
-    pub fn get_tuner_type(&self) -> String {
-        unsafe { from_tuner_type(rtlsdr_get_tuner_type(self.dev)) }
+    pub fn get_tuner_type(&self) -> TunerType {
+        unsafe { TunerType::from_raw(rtlsdr_get_tuner_type(self.dev) as u32) }
+    }
+
+    /// Returns what the dongle's tuner supports, e.g. for deciding which
+    /// settings to offer.
+    pub fn capabilities(&self) -> Capabilities {
+        self.get_tuner_type().capabilities()
     }
 
     /// Returns a list of supported tuner gains.
-    /// Values are in tenths of dB, e.g. 115 means 11.5 dB.
-    pub fn get_tuner_gains(&self) -> (Vec<i32>, Error) {
+    pub fn get_tuner_gains(&self) -> Result<Vec<TenthDb>> {
+        let op = "rtlsdr_get_tuner_gains";
         unsafe {
             let mut i = rtlsdr_get_tuner_gains(self.dev, ptr::null_mut());
             if i <= 0 {
-                // println!("error rtlsdr_get_tuner_gains <= 0: {}", i);
-                return (Vec::new(), get_err_msg(i));
+                return Err(Error::new(op, i));
             }
-            println!("rtlsdr_get_tuner_gains count: {}", i);
             let mut v = vec![0; i as usize];
             i = rtlsdr_get_tuner_gains(self.dev, v.as_mut_ptr());
-            let err = if i <= 0 {
-                Error::Unknown
-            } else {
-                Error::NoError
-            };
-
-            (v, err)
+            if i <= 0 {
+                return Err(Error::new(op, ERROR_UNKNOWN));
+            }
+            Ok(v.into_iter().map(TenthDb::new).collect())
         }
     }
 
@@ -457,64 +470,208 @@ impl Device {
     /// Valid values (in tenths of a dB) are:
     /// -10, 15, 40, 65, 90, 115, 140, 165, 190, 215, 240, 290,
     /// 340, 420, 430, 450, 470, 490
-    ///
-    /// Gain values are in tenths of dB, e.g. 115 means 11.5 dB.
-    pub fn set_tuner_gain(&self, gain: i32) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_tuner_gain(self.dev, gain)) }
+    pub fn set_tuner_gain(&self, gain: TenthDb) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_tuner_gain",
+                  rtlsdr_set_tuner_gain(self.dev, gain.tenths()))
+                .map(|_| ())
+        }
     }
 
     /// Returns the tuner gain.
+    pub fn get_tuner_gain(&self) -> TenthDb {
+        unsafe { TenthDb::new(rtlsdr_get_tuner_gain(self.dev)) }
+    }
+
+    /// Sets the gain, snapping manual gains to the nearest step the tuner
+    /// supports and switching between automatic and manual mode as needed.
+    /// Returns the gain actually applied.
+    pub fn set_gain(&self, gain: Gain) -> Result<Gain> {
+        tuner::set_gain(self, gain)
+    }
+
+    /// Sets the manual gain step nearest to `db` and returns it.
+    pub fn set_gain_db(&self, db: f64) -> Result<TenthDb> {
+        tuner::set_manual_gain(self, Gain::Manual(TenthDb::from_db(db)?))
+    }
+
+    /// Returns the gain in use, `Gain::Auto` or `Gain::Manual`.
+    pub fn get_gain(&self) -> Gain {
+        tuner::get_gain(self)
+    }
+
+    /// Applies the settings in `config` in dependency order and returns the
+    /// settings read back afterwards.
     ///
-    /// Gain values are in tenths of dB, e.g. 115 means 11.5 dB.
-    pub fn get_tuner_gain(&self) -> i32 {
-        unsafe { rtlsdr_get_tuner_gain(self.dev) }
+    /// When a setting fails the ones already made are restored where they
+    /// can be read back, the error tells which failed and whether the
+    /// rollback was complete.
+    pub fn apply(&self, config: &DeviceConfig) -> std::result::Result<DeviceConfig, ApplyError> {
+        config::apply(self, config)
+    }
+
+    /// Loads a TOML device profile, see `DeviceConfig`, and applies it.
+    #[cfg(feature = "serde")]
+    pub fn apply_profile<P: AsRef<std::path::Path>>(&self, path: P) -> Result<DeviceConfig> {
+        Ok(self.apply(&DeviceConfig::load(path)?)?)
     }
 
     /// Sets the device bandwidth.
-    pub fn set_tuner_bandwidth(&self, bw_hz: i32) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_tuner_bandwidth(self.dev, bw_hz as u32)) }
+    pub fn set_tuner_bandwidth(&self, bw: Hertz) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_tuner_bandwidth",
+                  rtlsdr_set_tuner_bandwidth(self.dev, bw.hz()))
+                .map(|_| ())
+        }
+    }
+
+    /// Enables or disables the R820T/R828D PLL dithering, disabling it
+    /// makes the tuning of several dongles coherent.
+    ///
+    /// Only the rtl-sdr-blog fork of librtlsdr has this, fails with
+    /// `NotSupported` otherwise, see `library_info`.
+    pub fn set_dithering(&self, dither: bool) -> Result<()> {
+        let set: ffi::SetDitheringFn = ffi::optional("rtlsdr_set_dithering")?;
+        unsafe { check("rtlsdr_set_dithering", set(self.dev, dither as c_int)).map(|_| ()) }
+    }
+
+    /// Switches the bias tee on or off, the one on GPIO 0 that powers the
+    /// antenna on the RTL-SDR Blog dongles.
+    ///
+    /// Only the rtl-sdr-blog fork and newer osmocom releases of librtlsdr
+    /// have this, fails with `NotSupported` otherwise, see `library_info`.
+    /// Bias tees still on are switched off when the device is closed.
+    pub fn set_bias_tee(&self, on: bool) -> Result<()> {
+        let op = "rtlsdr_set_bias_tee";
+        let set: ffi::SetBiasTeeFn = ffi::optional(op)?;
+        unsafe { check(op, set(self.dev, on as c_int))? };
+        self.record_bias_tee(0, on);
+        Ok(())
+    }
+
+    /// Returns whether the bias tee on GPIO 0 is on.
+    pub fn get_bias_tee(&self) -> bool {
+        self.get_bias_tee_gpio(0)
+    }
+
+    /// Switches a bias tee driven by `gpio`, 0 to `MAX_GPIO`, on or off,
+    /// for dongles that power the antenna through another GPIO.
+    ///
+    /// Fails with `InvalidParam` for other GPIOs and like `set_bias_tee`
+    /// otherwise.
+    pub fn set_bias_tee_gpio(&self, gpio: u8, on: bool) -> Result<()> {
+        let op = "rtlsdr_set_bias_tee_gpio";
+        if gpio > MAX_GPIO {
+            return Err(Error::with_kind(op, ErrorKind::InvalidParam, -2));
+        }
+        let set = ffi::optional::<ffi::SetBiasTeeGpioFn>(op);
+        // Fall back on rtlsdr_set_bias_tee, some builds only have that.
+        if set.is_err() && gpio == 0 {
+            return self.set_bias_tee(on);
+        }
+        unsafe { check(op, set?(self.dev, gpio as c_int, on as c_int))? };
+        self.record_bias_tee(gpio, on);
+        Ok(())
+    }
+
+    /// Returns whether the bias tee on `gpio` is on, as last set through
+    /// this device.
+    pub fn get_bias_tee_gpio(&self, gpio: u8) -> bool {
+        gpio <= MAX_GPIO && self.bias_tees.get() & (1 << gpio) != 0
+    }
+
+    fn record_bias_tee(&self, gpio: u8, on: bool) {
+        let bit = 1 << gpio;
+        let tees = self.bias_tees.get();
+        self.bias_tees.set(if on { tees | bit } else { tees & !bit });
     }
 
     /// Sets the intermediate frequency gain.
     ///
     /// Intermediate frequency gain stage number 1 to 6.
-    /// Gain values are in tenths of dB, e.g. -30 means -3.0 dB.
-    pub fn set_tuner_if_gain(&self, stage: i32, gains_tenths_db: i32) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_tuner_if_gain(self.dev, stage, gains_tenths_db)) }
-
+    pub fn set_tuner_if_gain(&self, stage: i32, gain: TenthDb) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_tuner_if_gain",
+                  rtlsdr_set_tuner_if_gain(self.dev, stage, gain.tenths()))
+                .map(|_| ())
+        }
     }
 
     /// Sets the gain mode, automatic or manual.
     /// Manual gain mode must be enabled for the gain setter function to work.
-    pub fn set_tuner_gain_mode(&self, manual_mode: bool) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_tuner_gain_mode(self.dev, manual_mode as i32)) }
+    pub fn set_tuner_gain_mode(&self, manual_mode: bool) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_tuner_gain_mode",
+                  rtlsdr_set_tuner_gain_mode(self.dev, manual_mode as i32))?;
+        }
+        self.manual_gain.set(manual_mode);
+        Ok(())
+    }
 
+    /// Returns true in manual gain mode, false with the tuner's AGC.
+    pub fn get_tuner_gain_mode(&self) -> bool {
+        self.manual_gain.get()
     }
 
     /// Sets the sample rate.
     ///
     /// When applicable, the baseband filters are also selected based
     /// on the requested sample rate.
-    pub fn set_sample_rate(&self, rate_hz: i32) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_sample_rate(self.dev, rate_hz as u32)) }
+    ///
+    /// The rate must be within 225001-300000 or 900001-3200000 samples per
+    /// second, see `check_sample_rate`.
+    pub fn set_sample_rate(&self, rate: SampleRate) -> Result<()> {
+        check_sample_rate(rate)?;
+        unsafe {
+            check("rtlsdr_set_sample_rate",
+                  rtlsdr_set_sample_rate(self.dev, rate.sps()))?;
+        }
+        self.rate.set(rate.sps());
+        Ok(())
+    }
+
+    /// Returns the sample rate, truncated to whole samples per second.
+    pub fn get_sample_rate(&self) -> SampleRate {
+        unsafe { SampleRate::new(rtlsdr_get_sample_rate(self.dev)) }
+    }
+
+    /// Returns the exact sample rate the resampler produces, derived from
+    /// the xtal frequency and frequency correction.
+    pub fn get_exact_sample_rate(&self) -> Result<f64> {
+        let (xtal, _) = self.get_xtal_freq()?;
+        let xtal = nominal_xtal(xtal, self.get_freq_correction());
+        Ok(exact_sample_rate(xtal, SampleRate::new(self.rate.get())))
     }
 
-    /// Returns the sample rate.
-    pub fn get_sample_rate(&self) -> i32 {
-        unsafe { rtlsdr_get_sample_rate(self.dev) }
+    /// Sets the valid sample rate nearest to `rate` that the resampler
+    /// produces exactly and returns it, for decoders that need exact timing.
+    pub fn set_exact_sample_rate(&self, rate: SampleRate) -> Result<SampleRate> {
+        let (xtal, _) = self.get_xtal_freq()?;
+        let xtal = nominal_xtal(xtal, self.get_freq_correction());
+        let rate = nearest_exact_sample_rate(xtal, rate)?;
+        self.set_sample_rate(rate)?;
+        Ok(rate)
     }
 
     /// Sets device to test mode.
     ///
     /// Test mode returns 8 bit counters instead of samples. Note,
     /// the counter is generated inside the device.
-    pub fn set_testmode(&self, test_mode: bool) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_testmode(self.dev, test_mode as i32)) }
+    pub fn set_testmode(&self, test_mode: bool) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_testmode",
+                  rtlsdr_set_testmode(self.dev, test_mode as i32))
+                .map(|_| ())
+        }
     }
 
     /// Sets the AGC mode.
-    pub fn set_agc_mode(&self, agc_mode: bool) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_agc_mode(self.dev, agc_mode as i32)) }
+    pub fn set_agc_mode(&self, agc_mode: bool) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_agc_mode",
+                  rtlsdr_set_agc_mode(self.dev, agc_mode as i32))
+                .map(|_| ())
+        }
     }
 
     /// Sets the direct sampling mode.
@@ -522,8 +679,12 @@ impl Device {
     /// When enabled, the IF mode of the device is activated, and
     /// set_center_freq() will control the IF-frequency of the DDC, which
     /// can be used to tune from 0 to 28.8 MHz (xtal frequency of the device).
-    pub fn set_direct_sampling(&self, mode: SamplingMode) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_direct_sampling(self.dev, mode as i32)) }
+    pub fn set_direct_sampling(&self, mode: SamplingMode) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_direct_sampling",
+                  rtlsdr_set_direct_sampling(self.dev, mode as i32))
+                .map(|_| ())
+        }
     }
 
     /// Returns the state of direct sampling mode.
@@ -540,146 +701,130 @@ impl Device {
 
     /// Sets the offset tuning mode for zero-IF tuners, which
     /// avoids problems caused by the DC offset of the ADCs and 1/f noise.
-    pub fn set_offset_tuning(&self, enable: bool) -> Error {
-        unsafe { get_err_msg(rtlsdr_set_offset_tuning(self.dev, enable as i32)) }
+    pub fn set_offset_tuning(&self, enable: bool) -> Result<()> {
+        unsafe {
+            check("rtlsdr_set_offset_tuning",
+                  rtlsdr_set_offset_tuning(self.dev, enable as i32))
+                .map(|_| ())
+        }
     }
 
     /// Returns the offset tuning mode.
-    pub fn get_offset_tuning(&self) -> Error {
-        unsafe { get_err_msg(rtlsdr_get_offset_tuning(self.dev)) }
+    pub fn get_offset_tuning(&self) -> Result<bool> {
+        unsafe {
+            check("rtlsdr_get_offset_tuning", rtlsdr_get_offset_tuning(self.dev)).map(|on| on != 0)
+        }
     }
 
     /// Resets the streaming buffer.
-    pub fn reset_buffer(&self) -> Error {
-        unsafe { get_err_msg(rtlsdr_reset_buffer(self.dev)) }
+    pub fn reset_buffer(&self) -> Result<()> {
+        unsafe { check("rtlsdr_reset_buffer", rtlsdr_reset_buffer(self.dev)).map(|_| ()) }
     }
 
-    /// Performs a synchronous read of samples and returns
-    /// the number of samples read.
-    pub fn read_sync(&self, len: i32) -> (Vec<u8>, i32, Error) {
+    /// Performs a synchronous read of samples and returns the
+    /// samples read, which may be fewer than requested.
+    pub fn read_sync(&self, len: i32) -> Result<Vec<u8>> {
+        if len < 0 {
+            return Err(Error::new("rtlsdr_read_sync", -2));
+        }
         let mut buf = vec![0u8; len as usize];
         let mut n_read: i32 = 0;
         unsafe {
-            let err = rtlsdr_read_sync(self.dev,
-                                       buf.as_mut_ptr() as *mut c_void,
-                                       len,
-                                       &mut n_read as *mut c_int);
-
-            (buf, n_read, get_err_msg(err))
+            check("rtlsdr_read_sync",
+                  rtlsdr_read_sync(self.dev,
+                                   buf.as_mut_ptr() as *mut c_void,
+                                   len,
+                                   &mut n_read as *mut c_int))?;
         }
-
+        buf.truncate(n_read as usize);
+        Ok(buf)
     }
 
-    /// Reads samples asynchronously. Note, this function will block until
-    /// canceled using cancel_async. ReadAsyncCbT is a package global variable.
+    /// Reads samples asynchronously, passing each buffer to `f`. Note, this
+    /// function will block until canceled using cancel_async or a Canceller.
     ///
     /// Optional buf_num buffer count, buf_num * buf_len = overall buffer size,
     /// set to 0 for default buffer count of 32.
     ///
     /// Optional buf_len buffer length, must be multiple of 512, set to 0 for
     /// default buffer length of 262,144 (16 * 32 * 512).
-    pub fn read_async(&self,
-                      f: ReadAsyncCbT,
-                      ctx: *mut c_void,
-                      buf_num: i32,
-                      buf_len: i32)
-                      -> Error {
-        unsafe { get_err_msg(rtlsdr_read_async(self.dev, f, ctx, buf_num as u32, buf_len as u32)) }
+    ///
+    /// The buffer is only borrowed for the duration of the call. A panic in
+    /// `f` cancels the read and is resumed once read_async returns.
+    pub fn read_async<F>(&self, buf_num: i32, buf_len: i32, mut f: F) -> Result<()>
+        where F: FnMut(&[u8])
+    {
+        self.read_async_until(buf_num, buf_len, |buf| {
+            f(buf);
+            AsyncControl::Continue
+        })
+    }
+
+    /// Same as read_async, but also stops reading once `f` returns
+    /// `AsyncControl::Stop`.
+    pub fn read_async_until<F>(&self, buf_num: i32, buf_len: i32, mut f: F) -> Result<()>
+        where F: FnMut(&[u8]) -> AsyncControl
+    {
+        let mut ctx = AsyncCtx {
+            f: &mut f,
+            dev: self.dev,
+            stopped: false,
+            panic: None,
+        };
+        let err = unsafe {
+            rtlsdr_read_async(self.dev,
+                              Some(read_async_trampoline),
+                              &mut ctx as *mut AsyncCtx as *mut c_void,
+                              buf_num as u32,
+                              buf_len as u32)
+        };
+        if let Some(p) = ctx.panic.take() {
+            panic::resume_unwind(p);
+        }
+        check("rtlsdr_read_async", err).map(|_| ())
     }
 
-    /// Cancels all pending asynchronous operations.
-    pub fn cancel_async(&self) -> Error {
-        unsafe { get_err_msg(rtlsdr_cancel_async(self.dev)) }
+    /// Reads samples asynchronously using a raw librtlsdr callback. Note,
+    /// this function will block until canceled using cancel_async.
+    ///
+    /// # Safety
+    ///
+    /// `f` must treat its buffer as borrowed from librtlsdr and `ctx` must
+    /// stay valid for whatever `f` does with it until this function returns.
+    pub unsafe fn read_async_raw(&self,
+                                 f: ReadAsyncCbT,
+                                 ctx: *mut c_void,
+                                 buf_num: i32,
+                                 buf_len: i32)
+                                 -> Result<()> {
+        check("rtlsdr_read_async",
+              rtlsdr_read_async(self.dev, f, ctx, buf_num as u32, buf_len as u32))
+            .map(|_| ())
+    }
 
+    /// Cancels all pending asynchronous operations.
+    pub fn cancel_async(&self) -> Result<()> {
+        unsafe { check("rtlsdr_cancel_async", rtlsdr_cancel_async(self.dev)).map(|_| ()) }
     }
 
     /// Reads the dongle's information from the EEPROM.
-    pub fn get_hw_info(&self) -> (HwInfo, Error) {
-        let mut have_serial = false;
-        let mut remote_wakeup = false;
-        let mut enable_ir = false;
-        let mut vendor_id = 0u16;
-        let mut product_id = 0u16;
-        let mut m: String = "".to_string();
-        let mut p: String = "".to_string();
-        let mut s: String = "".to_string();
-
-        let (data, mut err) = self.read_eeprom(0, EEPROM_SIZE as u16);
-        // println!("eeprom data: {:?}, error: {:?}", data, err);
-
-        if let Some(Error::NoError) = Some(err) {
-            if (data[0] != 0x28) || (data[1] != 0x32) {
-                err = get_err_msg(NO_VALID_EEPROM_HEADER);
-            } else {
-                vendor_id = (data[3] as u16) << 8 | data[2] as u16;
-                product_id = (data[5] as u16) << 8 | data[4] as u16;
-                // println!("vendor_id {}, product_id {}", vendor_id, product_id);
-
-                if data[6] == 0xA5 {
-                    have_serial = true;
-                }
-                if (data[7] & 0x01) == 0x01 {
-                    remote_wakeup = true;
-                }
-                if (data[7] & 0x02) == 0x02 {
-                    enable_ir = true;
-                }
-
-                let (mm, pp, ss, e) = get_string_descriptors(&data);
-                m = mm;
-                p = pp;
-                s = ss;
-                err = e;
-            }
-        }
-
-        let info = HwInfo {
-            have_serial: have_serial,
-            vendor_id: vendor_id,
-            product_id: product_id,
-            remote_wakeup: remote_wakeup,
-            enable_ir: enable_ir,
-            manufact: m,
-            product: p,
-            serial: s,
-        };
+    pub fn get_hw_info(&self) -> Result<HwInfo> {
+        eeprom::read_image(self)?.hw_info()
+    }
 
-        (info, err)
-    }
-
-    /// Write the dongle's information to the EEPROM.
-    pub fn set_hw_info(&self, info: &HwInfo) -> Error {
-        let mlen = info.manufact.len();
-        let plen = info.product.len();
-        let slen = info.serial.len();
-        let stored_len = STR_OFFSET_START + ((2 * mlen) + 2) + ((2 * plen) + 2) + ((2 * slen) + 2);
-        let mut data = vec![0u8; stored_len];
-
-        data[0] = 0x28u8;
-        data[1] = 0x32u8;
-        data[2] = info.vendor_id as u8;
-        data[3] = (info.vendor_id >> 8) as u8;
-        data[4] = info.product_id as u8;
-        data[5] = (info.product_id >> 8) as u8;
-        data[6] = 0x00u8;
-        data[7] = 0x00u8;
-        data[8] = 0x00u8;
-
-        if info.have_serial == true {
-            data[6] = 0xA5u8;
-        }
-        if info.remote_wakeup == true {
-            data[7] = data[7] | 0x01;
-        }
-        if info.enable_ir == true {
-            data[7] = data[7] | 0x02;
-        }
+    /// Write the dongle's information to the EEPROM, leaving the rest of it
+    /// as it is.
+    pub fn set_hw_info(&self, info: &HwInfo) -> Result<()> {
+        eeprom::set_hw_info(self, info)
+    }
 
-        let mut err = set_string_descriptors(&info, &mut data);
-        if let Some(Error::NoError) = Some(err) {
-            err = self.write_eeprom(data, 0);
-        }
+    /// Reads the full EEPROM.
+    pub fn read_eeprom_image(&self) -> Result<EepromImage> {
+        eeprom::read_image(self)
+    }
 
-        err
+    /// Writes a full EEPROM image.
+    pub fn write_eeprom_image(&self, image: &EepromImage) -> Result<()> {
+        eeprom::write_image(self, image)
     }
 }
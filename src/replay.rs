@@ -0,0 +1,433 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Replay of raw `.cu8` captures.
+//!
+//! `FileSource` plays back the interleaved 8-bit offset IQ written by
+//! `rtl_sdr` through the `SdrSource` API, so recordings run through the same
+//! code as a live dongle. The files carry no metadata, the sample rate and
+//! center frequency are given in `ReplayOptions`.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{AsyncControl, Canceller, Capabilities, Error, Hertz, Ppm, Result, SampleRate,
+            SamplingMode, SdrSource, TenthDb, TunerType, CRYSTAL_FREQ, DEFAULT_BUF_LENGTH,
+            MIN_BUF_LENGTH};
+use super::source::Running;
+
+/// How fast samples are delivered.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pacing {
+    /// At the recorded sample rate, like a live dongle.
+    RealTime,
+    /// As fast as the file can be read.
+    Unpaced,
+}
+
+/// Replay settings.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ReplayOptions {
+    /// Sample rate the file was recorded at.
+    pub sample_rate: SampleRate,
+    /// Center frequency the file was recorded at, zero if unknown.
+    pub center_freq: Hertz,
+    pub pacing: Pacing,
+    /// Restart from the beginning at the end of the file.
+    pub looping: bool,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> ReplayOptions {
+        ReplayOptions {
+            sample_rate: SampleRate::new(2_048_000),
+            center_freq: Hertz::new(0),
+            pacing: Pacing::Unpaced,
+            looping: false,
+        }
+    }
+}
+
+struct ReplayState {
+    file: File,
+    // File length and read position in bytes, always whole IQ pairs.
+    len: u64,
+    pos: u64,
+    opts: ReplayOptions,
+    // Real-time reference, the instant `clock.1` samples had been delivered.
+    clock: Option<(Instant, u64)>,
+    gain: TenthDb,
+    manual_gain: bool,
+    ppm: Ppm,
+}
+
+/// A `.cu8` file played back as a device, see the module documentation.
+pub struct FileSource {
+    state: Mutex<ReplayState>,
+    running: Arc<AtomicBool>,
+    cancel: Arc<AtomicBool>,
+}
+
+fn not_supported(op: &'static str) -> Error {
+    Error::new(op, -12)
+}
+
+fn invalid(op: &'static str) -> Error {
+    Error::new(op, -2)
+}
+
+impl ReplayState {
+    fn seek(&mut self, sample: u64) -> Result<()> {
+        let pos = sample.checked_mul(2).filter(|&pos| pos <= self.len);
+        let pos = pos.ok_or_else(|| invalid("FileSource::seek"))?;
+        self.file.seek(SeekFrom::Start(pos)).map_err(|e| Error::from_io("FileSource::seek", &e))?;
+        self.pos = pos;
+        self.clock = None;
+        Ok(())
+    }
+
+    // Reads up to `len` bytes, wrapping around when looping. Returns less
+    // only at the end of a non-looping file.
+    fn read(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len & !1];
+        let mut n = 0;
+        while n < buf.len() {
+            if self.pos >= self.len {
+                if !self.opts.looping || self.len == 0 {
+                    break;
+                }
+                self.file
+                    .seek(SeekFrom::Start(0))
+                    .map_err(|e| Error::from_io("FileSource::read", &e))?;
+                self.pos = 0;
+            }
+            let want = (buf.len() - n).min((self.len - self.pos) as usize);
+            let got = self.file
+                .read(&mut buf[n..n + want])
+                .map_err(|e| Error::from_io("FileSource::read", &e))?;
+            if got == 0 {
+                // Truncated underneath us.
+                self.len = self.pos;
+                continue;
+            }
+            n += got;
+            self.pos += got as u64;
+        }
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    // How long to wait before handing out `bytes` more, in real-time mode.
+    fn pace(&mut self, bytes: usize) -> Option<Duration> {
+        let rate = self.opts.sample_rate.as_f64();
+        if self.opts.pacing != Pacing::RealTime || rate == 0.0 {
+            return None;
+        }
+        let now = Instant::now();
+        let (start, sent) = *self.clock.get_or_insert((now, 0));
+        let sent = sent + bytes as u64 / 2;
+        self.clock = Some((start, sent));
+        let due = start + Duration::from_secs_f64(sent as f64 / rate);
+        due.checked_duration_since(now)
+    }
+}
+
+impl FileSource {
+    /// Opens a capture for replay.
+    pub fn open<P: AsRef<Path>>(path: P, opts: ReplayOptions) -> Result<FileSource> {
+        let file = File::open(path).map_err(|e| Error::from_io("FileSource::open", &e))?;
+        let len = file.metadata().map_err(|e| Error::from_io("FileSource::open", &e))?.len();
+        Ok(FileSource {
+            state: Mutex::new(ReplayState {
+                file,
+                len: len & !1,
+                pos: 0,
+                opts,
+                clock: None,
+                gain: TenthDb::default(),
+                manual_gain: false,
+                ppm: Ppm::default(),
+            }),
+            running: Arc::new(AtomicBool::new(false)),
+            cancel: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ReplayState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the length of the capture in samples.
+    pub fn len(&self) -> u64 {
+        self.lock().len / 2
+    }
+
+    /// Returns true if the capture holds no samples.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the length of the capture at the recorded sample rate, zero
+    /// if the rate is unknown.
+    pub fn duration(&self) -> Duration {
+        let state = self.lock();
+        let rate = state.opts.sample_rate.as_f64();
+        if rate == 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(state.len as f64 / 2.0 / rate)
+    }
+
+    /// Returns the sample offset of the next read.
+    pub fn position(&self) -> u64 {
+        self.lock().pos / 2
+    }
+
+    /// Moves to the given sample offset, which may be the end of the file.
+    pub fn seek_sample(&self, sample: u64) -> Result<()> {
+        self.lock().seek(sample)
+    }
+
+    /// Moves to the given time offset at the recorded sample rate.
+    pub fn seek_time(&self, offset: Duration) -> Result<()> {
+        let mut state = self.lock();
+        let sample = (offset.as_secs_f64() * state.opts.sample_rate.as_f64()).round() as u64;
+        state.seek(sample)
+    }
+
+    fn read(&self, len: usize) -> Result<Vec<u8>> {
+        let (buf, pause) = {
+            let mut state = self.lock();
+            let buf = state.read(len)?;
+            let pause = state.pace(buf.len());
+            (buf, pause)
+        };
+        if let Some(pause) = pause {
+            thread::sleep(pause);
+        }
+        Ok(buf)
+    }
+}
+
+impl SdrSource for FileSource {
+    fn set_xtal_freq(&self, _rtl_freq: Hertz, _tuner_freq: Hertz) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_xtal_freq(&self) -> Result<(Hertz, Hertz)> {
+        let xtal = Hertz::new(CRYSTAL_FREQ as u32);
+        Ok((xtal, xtal))
+    }
+
+    fn get_usb_strings(&self) -> Result<(String, String, String)> {
+        Err(not_supported("rtlsdr_get_usb_strings"))
+    }
+
+    fn write_eeprom(&self, _data: Vec<u8>, _offset: u8) -> Result<()> {
+        Err(not_supported("rtlsdr_write_eeprom"))
+    }
+
+    fn read_eeprom(&self, _offset: u8, _len: u16) -> Result<Vec<u8>> {
+        Err(not_supported("rtlsdr_read_eeprom"))
+    }
+
+    /// Only the recorded frequency can be tuned, any if it's unknown.
+    fn set_center_freq(&self, freq: Hertz) -> Result<()> {
+        let mut state = self.lock();
+        match state.opts.center_freq.hz() {
+            0 => state.opts.center_freq = freq,
+            recorded if recorded == freq.hz() => {}
+            _ => return Err(not_supported("rtlsdr_set_center_freq")),
+        }
+        Ok(())
+    }
+
+    fn get_center_freq(&self) -> Hertz {
+        self.lock().opts.center_freq
+    }
+
+    fn set_freq_correction(&self, ppm: Ppm) -> Result<()> {
+        self.lock().ppm = ppm;
+        Ok(())
+    }
+
+    fn get_freq_correction(&self) -> Ppm {
+        self.lock().ppm
+    }
+
+    fn get_tuner_type(&self) -> TunerType {
+        TunerType::Unknown
+    }
+
+    fn get_tuner_gains(&self) -> Result<Vec<TenthDb>> {
+        Ok(Vec::new())
+    }
+
+    fn set_tuner_gain(&self, gain: TenthDb) -> Result<()> {
+        self.lock().gain = gain;
+        Ok(())
+    }
+
+    fn get_tuner_gain(&self) -> TenthDb {
+        self.lock().gain
+    }
+
+    fn set_tuner_bandwidth(&self, _bw: Hertz) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_tuner_if_gain(&self, _stage: i32, _gain: TenthDb) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_tuner_gain_mode(&self, manual_mode: bool) -> Result<()> {
+        self.lock().manual_gain = manual_mode;
+        Ok(())
+    }
+
+    fn get_tuner_gain_mode(&self) -> bool {
+        self.lock().manual_gain
+    }
+
+    /// Only the recorded sample rate can be set.
+    fn set_sample_rate(&self, rate: SampleRate) -> Result<()> {
+        if self.lock().opts.sample_rate != rate {
+            return Err(not_supported("rtlsdr_set_sample_rate"));
+        }
+        Ok(())
+    }
+
+    fn get_sample_rate(&self) -> SampleRate {
+        self.lock().opts.sample_rate
+    }
+
+    fn get_exact_sample_rate(&self) -> Result<f64> {
+        Ok(self.lock().opts.sample_rate.as_f64())
+    }
+
+    fn set_testmode(&self, test_mode: bool) -> Result<()> {
+        if test_mode {
+            return Err(not_supported("rtlsdr_set_testmode"));
+        }
+        Ok(())
+    }
+
+    fn set_agc_mode(&self, _agc_mode: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_direct_sampling(&self, mode: SamplingMode) -> Result<()> {
+        if mode != SamplingMode::None {
+            return Err(not_supported("rtlsdr_set_direct_sampling"));
+        }
+        Ok(())
+    }
+
+    fn get_direct_sampling(&self) -> SamplingMode {
+        SamplingMode::None
+    }
+
+    fn set_offset_tuning(&self, enable: bool) -> Result<()> {
+        if enable {
+            return Err(not_supported("rtlsdr_set_offset_tuning"));
+        }
+        Ok(())
+    }
+
+    fn get_offset_tuning(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn reset_buffer(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Reads up to `len` bytes, fewer at the end of a non-looping file and
+    /// none once it's exhausted.
+    fn read_sync(&self, len: i32) -> Result<Vec<u8>> {
+        if len < 0 {
+            return Err(invalid("rtlsdr_read_sync"));
+        }
+        self.read(len as usize)
+    }
+
+    /// Delivers `buf_len` byte buffers, falling back to DEFAULT_BUF_LENGTH
+    /// when it isn't a multiple of MIN_BUF_LENGTH as librtlsdr does. A
+    /// non-looping replay returns after the last, possibly short, buffer.
+    fn read_async_until(&self,
+                        _buf_num: i32,
+                        buf_len: i32,
+                        f: &mut dyn FnMut(&[u8]) -> AsyncControl)
+                        -> Result<()> {
+        let _running = Running::start(&self.running, &self.cancel)?;
+        let buf_len = if buf_len > 0 && buf_len % MIN_BUF_LENGTH == 0 {
+            buf_len as usize
+        } else {
+            DEFAULT_BUF_LENGTH as usize
+        };
+        let mut res = Ok(());
+        while !self.cancel.load(Ordering::SeqCst) {
+            let buf = match self.read(buf_len) {
+                Ok(buf) => buf,
+                Err(e) => {
+                    res = Err(e);
+                    break;
+                }
+            };
+            if buf.is_empty() || f(&buf) == AsyncControl::Stop || buf.len() < buf_len {
+                break;
+            }
+        }
+        res
+    }
+
+    fn cancel_async(&self) -> Result<()> {
+        self.canceller().cancel_async()
+    }
+
+    fn canceller(&self) -> Canceller {
+        let running = self.running.clone();
+        let cancel = self.cancel.clone();
+        Canceller::new(move || {
+            if !running.load(Ordering::SeqCst) {
+                return Err(invalid("rtlsdr_cancel_async"));
+            }
+            cancel.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    /// Only the recorded center frequency and sample rate, with no gain or
+    /// mode controls.
+    fn capabilities(&self) -> Capabilities {
+        let opts = self.lock().opts;
+        let freq = match opts.center_freq.hz() {
+            0 => Hertz::new(0)..=Hertz::new(u32::MAX),
+            _ => opts.center_freq..=opts.center_freq,
+        };
+        Capabilities {
+            tuner: TunerType::Unknown,
+            freq_ranges: vec![freq],
+            gains: Vec::new(),
+            if_gain_stages: 0,
+            offset_tuning: false,
+            bandwidth: false,
+            direct_sampling: false,
+            sample_rates: vec![opts.sample_rate..=opts.sample_rate],
+        }
+    }
+}
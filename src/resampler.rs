@@ -0,0 +1,83 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The RTL2832 resampler.
+//!
+//! The sample rate is the xtal frequency times 2^22 divided by a 28-bit
+//! resampler ratio, the ratio's two low bits are dropped. Most requested
+//! rates can't be hit exactly, librtlsdr picks the ratio below the ideal one
+//! and truncates the resulting rate to report it. The ratio is derived from
+//! the nominal xtal frequency, the demodulator's sample frequency correction
+//! takes care of the ppm error.
+
+use super::{Error, ErrorKind, Hertz, Ppm, Result, SampleRate};
+
+/// Sample rates the resampler accepts, in samples per second.
+pub(crate) const SAMPLE_RATE_RANGES: &[(u32, u32)] = &[(225_001, 300_000), (900_001, 3_200_000)];
+
+// The ratio librtlsdr programs for `rate`, with its bit 27 sign extension.
+fn ratio(xtal: u32, rate: u32) -> u64 {
+    let ratio = (((xtal as u64) << 22) / rate as u64) & 0x0fff_fffc;
+    ratio | ((ratio & 0x0800_0000) << 1)
+}
+
+/// Checks `rate` against the ranges the resampler accepts, 225001-300000
+/// and 900001-3200000 samples per second.
+pub fn check_sample_rate(rate: SampleRate) -> Result<()> {
+    if SAMPLE_RATE_RANGES.iter().any(|&(lo, hi)| rate.sps() >= lo && rate.sps() <= hi) {
+        Ok(())
+    } else {
+        Err(Error::with_kind("rtlsdr_set_sample_rate", ErrorKind::InvalidParam, -2))
+    }
+}
+
+/// Returns the exact rate the resampler produces when `rate` is requested
+/// with a nominal `xtal` frequency. get_sample_rate reports it truncated.
+pub fn exact_sample_rate(xtal: Hertz, rate: SampleRate) -> f64 {
+    if xtal.hz() == 0 || rate.sps() == 0 {
+        return 0.0;
+    }
+    ((xtal.hz() as u64) << 22) as f64 / ratio(xtal.hz(), rate.sps()) as f64
+}
+
+/// Returns the valid rate closest to `rate` that the resampler produces
+/// exactly with a nominal `xtal` frequency, e.g. 2.048 Msps but not
+/// 2.083334 Msps at 28.8 MHz.
+pub fn nearest_exact_sample_rate(xtal: Hertz, rate: SampleRate) -> Result<SampleRate> {
+    let x = (xtal.hz() as u64) << 22;
+    let exact = |r: u32| {
+        let ratio = ratio(xtal.hz(), r);
+        ratio != 0 && x % ratio == 0 && x / ratio == r as u64
+    };
+    SAMPLE_RATE_RANGES.iter()
+        .filter_map(|&(lo, hi)| {
+            // Search outwards from the closest point of each range.
+            let start = rate.sps().clamp(lo, hi);
+            let below = (lo..=start).rev().find(|&r| exact(r));
+            let above = (start..=hi).find(|&r| exact(r));
+            below.into_iter().chain(above).min_by_key(|&r| (r as i64 - rate.sps() as i64).abs())
+        })
+        .min_by_key(|&r| (r as i64 - rate.sps() as i64).abs())
+        .map(SampleRate::new)
+        .ok_or_else(|| Error::with_kind("nearest_exact_sample_rate", ErrorKind::InvalidParam, -2))
+}
+
+/// Takes the ppm correction back out of an xtal frequency reported by
+/// get_xtal_freq.
+pub(crate) fn nominal_xtal(xtal: Hertz, ppm: Ppm) -> Hertz {
+    let corr = 1.0 + ppm.ppm() as f64 / 1e6;
+    // get_xtal_freq truncates, find the nominal value that reproduces it.
+    let guess = (xtal.as_f64() / corr).round() as u32;
+    [guess, guess.saturating_add(1), guess.saturating_sub(1)]
+        .iter()
+        .cloned()
+        .find(|&n| (n as f64 * corr) as u32 == xtal.hz())
+        .map(Hertz::new)
+        .unwrap_or(Hertz::new(guess))
+}
@@ -0,0 +1,221 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serial number assignment for multi-dongle setups.
+//!
+//! Most dongles ship with serial "00000001", so selecting them by serial
+//! only works once they've been given unique ones. `plan` finds the dongles
+//! sharing a serial and proposes new serials, `assign` writes them: it backs
+//! each EEPROM up to a file first, writes the new serial with `set_hw_info`
+//! and re-reads the EEPROM to verify it. A dongle reports its new serial
+//! over USB only once it has been replugged, the report says which still
+//! need it.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::OpenOptions;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Error, ErrorKind, Result, SdrSource, EEPROM_SIZE, MAX_STR_SIZE};
+
+/// How `plan` picks new serials.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    /// 8 digit numbers counting up from `start`, skipping those in use,
+    /// e.g. "00000002".
+    Sequential { start: u32 },
+    /// Random 8 digit numbers.
+    Random,
+    /// Serials given by dongle index, dongles not in the map keep theirs.
+    Mapped(BTreeMap<usize, String>),
+}
+
+/// A serial change for the dongle at `index`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub index: usize,
+    pub old: String,
+    pub new: String,
+}
+
+/// The outcome of a `Change` made by `assign`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub change: Change,
+    /// Where the EEPROM was backed up before writing.
+    pub backup: PathBuf,
+    /// Whether the dongle still reports its old serial over USB, unplug
+    /// and replug it to make the new one take effect.
+    pub needs_replug: bool,
+}
+
+fn invalid(op: &'static str) -> Error {
+    Error::with_kind(op, ErrorKind::InvalidParam, -2)
+}
+
+/// Returns the serials shared by more than one dongle.
+pub fn duplicates(serials: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut dups: Vec<String> = serials.iter().filter(|s| !seen.insert(*s)).cloned().collect();
+    dups.sort();
+    dups.dedup();
+    dups
+}
+
+/// Checks that `serial` can be stored in the EEPROM: 1 to 35 printable
+/// ASCII characters.
+pub fn check_serial(serial: &str) -> Result<()> {
+    if serial.is_empty() || serial.len() > MAX_STR_SIZE ||
+       !serial.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(invalid("check_serial"));
+    }
+    Ok(())
+}
+
+// A random 8 digit serial, std's hasher keys are seeded randomly.
+fn random_serial() -> String {
+    let mut h = RandomState::new().build_hasher();
+    h.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos()));
+    format!("{:08}", h.finish() % 100_000_000)
+}
+
+/// Proposes serial changes for dongles with the `serials` given, in index
+/// order.
+///
+/// `Sequential` and `Random` change every dongle but the first sharing a
+/// serial, `Mapped` changes the mapped dongles. Fails with `InvalidParam`
+/// if a mapped index or serial is invalid, or if the serials would still
+/// not be unique afterwards.
+pub fn plan(serials: &[String], scheme: &Scheme) -> Result<Vec<Change>> {
+    let op = "serial::plan";
+    let mut new: Vec<String> = serials.to_vec();
+    let mut used: HashSet<String> = serials.iter().cloned().collect();
+    match *scheme {
+        Scheme::Mapped(ref map) => {
+            for (&index, serial) in map {
+                check_serial(serial)?;
+                *new.get_mut(index).ok_or_else(|| invalid(op))? = serial.clone();
+            }
+        }
+        Scheme::Sequential { .. } | Scheme::Random => {
+            let mut next = match *scheme {
+                Scheme::Sequential { start } => start,
+                _ => 0,
+            };
+            let mut seen = HashSet::new();
+            for serial in new.iter_mut() {
+                if seen.insert(serial.clone()) {
+                    continue;
+                }
+                let fresh = loop {
+                    let candidate = match *scheme {
+                        Scheme::Random => random_serial(),
+                        _ => {
+                            let s = format!("{:08}", next);
+                            next = next.checked_add(1).ok_or_else(|| invalid(op))?;
+                            s
+                        }
+                    };
+                    if !used.contains(&candidate) {
+                        break candidate;
+                    }
+                };
+                used.insert(fresh.clone());
+                *serial = fresh;
+            }
+        }
+    }
+    if !duplicates(&new).is_empty() {
+        return Err(invalid(op));
+    }
+    Ok(serials.iter()
+        .zip(new)
+        .enumerate()
+        .filter(|&(_, (old, ref new))| old != new)
+        .map(|(index, (old, new))| {
+            Change {
+                index,
+                old: old.clone(),
+                new,
+            }
+        })
+        .collect())
+}
+
+/// Writes the full EEPROM of `dev` to a new file in `dir`, named after the
+/// dongle's index, serial and the time, and returns its path.
+pub fn backup_eeprom<S: SdrSource + ?Sized>(dev: &S,
+                                           index: usize,
+                                           serial: &str,
+                                           dir: &Path)
+                                           -> Result<PathBuf> {
+    let op = "serial::backup_eeprom";
+    let data = dev.read_eeprom(0, EEPROM_SIZE as u16)?;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let name: String =
+        serial.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    let path = dir.join(format!("eeprom-{}-{}-{}.bin", index, name, secs));
+    // Never overwrite an earlier backup, it may be the only original.
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(&data))
+        .map_err(|e| Error::from_io(op, &e))?;
+    Ok(path)
+}
+
+/// Makes the `changes` on `dongles`, given as (index, dongle) pairs so only
+/// the dongles being changed need to be opened.
+///
+/// Each EEPROM is backed up to `backup_dir` before it's written and re-read
+/// afterwards. With `clear_trailer` the EEPROM bytes after the strings are
+/// erased, see `EepromImage::clear_trailer`, otherwise a longer serial fails
+/// with `TrailerNotErased` if they aren't. Stops at the first failure, fails
+/// with `InvalidParam` if a change's dongle isn't given and with `Io` if a
+/// re-read EEPROM doesn't hold the new serial.
+pub fn assign<S: SdrSource + ?Sized>(dongles: &[(usize, &S)],
+                                     changes: &[Change],
+                                     backup_dir: &Path,
+                                     clear_trailer: bool)
+                                     -> Result<Vec<Report>> {
+    let op = "serial::assign";
+    let mut reports = Vec::new();
+    for change in changes {
+        check_serial(&change.new)?;
+        let dev = dongles.iter()
+            .find(|&&(index, _)| index == change.index)
+            .map(|&(_, dev)| dev)
+            .ok_or_else(|| invalid(op))?;
+        let mut image = dev.read_eeprom_image()?;
+        let mut info = image.hw_info()?;
+        let backup = backup_eeprom(dev, change.index, &info.serial, backup_dir)?;
+        info.serial = change.new.clone();
+        // Without this the dongle doesn't report a serial at all.
+        info.have_serial = true;
+        image.set_hw_info(&info)?;
+        if clear_trailer {
+            image.clear_trailer();
+        }
+        dev.write_eeprom_image(&image)?;
+        if dev.get_hw_info()?.serial != change.new {
+            return Err(Error::with_kind(op, ErrorKind::Io, -1));
+        }
+        let needs_replug = dev.get_usb_strings().map_or(true, |(_, _, s)| s != change.new);
+        reports.push(Report {
+            change: change.clone(),
+            backup,
+            needs_replug,
+        });
+    }
+    Ok(reports)
+}
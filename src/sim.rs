@@ -0,0 +1,581 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! In-process simulated dongle.
+//!
+//! `SimDevice` implements `SdrSource` the way librtlsdr drives a real
+//! RTL2832U: per-tuner gain tables and tuning ranges, the resampler's
+//! sample rate ranges and quantisation, xtal and ppm handling, a 256 byte
+//! EEPROM and the test mode counter. Samples are 8-bit offset IQ generated
+//! from a scene of `Signal`s placed at absolute frequencies, so what shows up
+//! in the baseband follows the center frequency, sample rate, gain and ppm
+//! error. Output is deterministic for a given seed.
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use super::{check_sample_rate, exact_sample_rate, AsyncControl, Canceller, EepromImage, Error,
+            Hertz, Ppm, Result, SampleRate, SamplingMode, SdrSource, TenthDb, TunerType,
+            CRYSTAL_FREQ, DEFAULT_BUF_LENGTH, EEPROM_SIZE, MAX_GPIO};
+use super::source::Running;
+
+// librtlsdr's allowed deviation of the RTL2832 xtal from 28.8 MHz.
+const XTAL_TOLERANCE: u32 = 1000;
+
+/// Signals making up the simulated RF scene.
+///
+/// Frequencies are absolute RF frequencies in Hz, amplitudes are relative to
+/// the ADC full scale at 0 dB gain. Anything driven past full scale clips at
+/// the ADC just like a real overloaded dongle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signal {
+    /// A continuous carrier.
+    Tone { freq_hz: f64, amplitude: f64 },
+    /// White gaussian noise across the whole band, `amplitude` is the
+    /// standard deviation per component.
+    Noise { amplitude: f64 },
+    /// An AM carrier modulated by a tone.
+    Am {
+        freq_hz: f64,
+        amplitude: f64,
+        mod_freq_hz: f64,
+        depth: f64,
+    },
+    /// An FM carrier modulated by a tone.
+    Fm {
+        freq_hz: f64,
+        amplitude: f64,
+        mod_freq_hz: f64,
+        deviation_hz: f64,
+    },
+    /// A carrier keyed on for `on_s` seconds every `period_s` seconds.
+    Burst {
+        freq_hz: f64,
+        amplitude: f64,
+        period_s: f64,
+        on_s: f64,
+    },
+}
+
+struct SimState {
+    tuner: TunerType,
+    rtl_xtal: u32,
+    tuner_xtal: u32,
+    ppm: i32,
+    ppm_error: f64,
+    freq: u32,
+    rate: u32,
+    real_rate: f64,
+    manual_gain: bool,
+    gain: i32,
+    if_gains: [i32; 6],
+    bandwidth: u32,
+    agc: bool,
+    direct_sampling: SamplingMode,
+    offset_tuning: bool,
+    // One bit per GPIO with its bias tee on.
+    bias_tees: u8,
+    testmode: bool,
+    counter: u8,
+    eeprom: Vec<u8>,
+    usb_strings: (String, String, String),
+    signals: Vec<Signal>,
+    // Sample clock, advances with every generated IQ pair.
+    sample: u64,
+    rng: u64,
+    realtime: bool,
+}
+
+/// A simulated dongle, see the module documentation.
+pub struct SimDevice {
+    state: Mutex<SimState>,
+    running: Arc<AtomicBool>,
+    cancel: Arc<AtomicBool>,
+}
+
+/// A generic Realtek EEPROM image, as shipped on most dongles.
+fn default_eeprom() -> Vec<u8> {
+    let mut data = vec![0xffu8; EEPROM_SIZE as usize];
+    data[..9].copy_from_slice(&[0x28, 0x32, 0xda, 0x0b, 0x38, 0x28, 0xa5, 0x16, 0x02]);
+    let mut pos = 9;
+    for s in &["Realtek", "RTL2838UHIDIR", "00000001"] {
+        data[pos] = (s.len() * 2 + 2) as u8;
+        data[pos + 1] = 0x03;
+        pos += 2;
+        for b in s.bytes() {
+            data[pos] = b;
+            data[pos + 1] = 0x00;
+            pos += 2;
+        }
+    }
+    data
+}
+
+fn invalid(op: &'static str) -> Error {
+    Error::new(op, -2)
+}
+
+impl SimState {
+    fn lo_error_hz(&self) -> f64 {
+        // The uncorrected crystal error shifts everything the tuner sees.
+        (self.ppm_error - self.ppm as f64) * 1e-6 * self.freq as f64
+    }
+
+    fn gain_scale(&self) -> f64 {
+        if self.manual_gain {
+            10f64.powf(self.gain as f64 / 200.0)
+        } else {
+            1.0
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn gaussian(&mut self) -> f64 {
+        let u1 = ((self.next_u64() >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let u2 = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        if self.testmode {
+            for b in buf.iter_mut() {
+                *b = self.counter;
+                self.counter = self.counter.wrapping_add(1);
+            }
+            return;
+        }
+
+        let fs = self.real_rate;
+        let center = self.freq as f64 + self.lo_error_hz();
+        let scale = self.gain_scale();
+        let signals = self.signals.clone();
+        for iq in buf.chunks_mut(2) {
+            let t = self.sample as f64 / fs;
+            let (mut i, mut q) = (0.0, 0.0);
+            for sig in &signals {
+                let (freq_hz, a, phase) = match *sig {
+                    Signal::Noise { amplitude } => {
+                        i += amplitude * self.gaussian();
+                        q += amplitude * self.gaussian();
+                        continue;
+                    }
+                    Signal::Tone { freq_hz, amplitude } => (freq_hz, amplitude, 0.0),
+                    Signal::Am { freq_hz, amplitude, mod_freq_hz, depth } => {
+                        let env = 1.0 + depth * (2.0 * PI * mod_freq_hz * t).cos();
+                        (freq_hz, amplitude * env / (1.0 + depth), 0.0)
+                    }
+                    Signal::Fm { freq_hz, amplitude, mod_freq_hz, deviation_hz } => {
+                        let phase = deviation_hz / mod_freq_hz * (2.0 * PI * mod_freq_hz * t).sin();
+                        (freq_hz, amplitude, phase)
+                    }
+                    Signal::Burst { freq_hz, amplitude, period_s, on_s } => {
+                        if t % period_s >= on_s {
+                            continue;
+                        }
+                        (freq_hz, amplitude, 0.0)
+                    }
+                };
+                // Anything outside the baseband is removed by the decimation filter.
+                let offset = freq_hz - center;
+                if offset.abs() > fs / 2.0 {
+                    continue;
+                }
+                let phase = 2.0 * PI * offset * t + phase;
+                i += a * phase.cos();
+                q += a * phase.sin();
+            }
+            iq[0] = to_u8(i * scale);
+            if iq.len() > 1 {
+                iq[1] = to_u8(q * scale);
+            }
+            self.sample += 1;
+        }
+    }
+}
+
+fn to_u8(x: f64) -> u8 {
+    (x * 127.5 + 127.5).round().clamp(0.0, 255.0) as u8
+}
+
+impl SimDevice {
+    /// Creates a simulated dongle with an R820T tuner.
+    pub fn new() -> SimDevice {
+        SimDevice::with_tuner(TunerType::R820T)
+    }
+
+    /// Creates a simulated dongle with the given tuner.
+    pub fn with_tuner(tuner: TunerType) -> SimDevice {
+        let mut state = SimState {
+            tuner,
+            rtl_xtal: CRYSTAL_FREQ as u32,
+            tuner_xtal: CRYSTAL_FREQ as u32,
+            ppm: 0,
+            ppm_error: 0.0,
+            freq: 0,
+            rate: 0,
+            real_rate: 2_048_000.0,
+            manual_gain: false,
+            gain: 0,
+            if_gains: [0; 6],
+            bandwidth: 0,
+            agc: false,
+            direct_sampling: SamplingMode::None,
+            offset_tuning: false,
+            bias_tees: 0,
+            testmode: false,
+            counter: 0,
+            eeprom: default_eeprom(),
+            usb_strings: (String::new(), String::new(), String::new()),
+            signals: Vec::new(),
+            sample: 0,
+            rng: 0x853c_49e6_748f_ea9b,
+            realtime: false,
+        };
+        state.usb_strings = usb_strings(&state.eeprom);
+        SimDevice {
+            state: Mutex::new(state),
+            running: Arc::new(AtomicBool::new(false)),
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds a signal to the scene.
+    pub fn add_signal(&self, signal: Signal) {
+        self.lock().signals.push(signal);
+    }
+
+    /// Replaces the scene.
+    pub fn set_signals(&self, signals: Vec<Signal>) {
+        self.lock().signals = signals;
+    }
+
+    /// Seeds the noise generator.
+    pub fn set_seed(&self, seed: u64) {
+        // xorshift state must never be zero.
+        self.lock().rng = seed | 1;
+    }
+
+    /// Sets the crystal's actual error in ppm, which set_freq_correction is
+    /// expected to cancel out.
+    pub fn set_ppm_error(&self, ppm: f64) {
+        self.lock().ppm_error = ppm;
+    }
+
+    /// Paces read_async and read_sync at the configured sample rate instead
+    /// of generating samples as fast as possible.
+    pub fn set_realtime(&self, realtime: bool) {
+        self.lock().realtime = realtime;
+    }
+
+    /// Simulates unplugging and replugging the dongle, the USB strings are
+    /// re-read from the EEPROM.
+    pub fn replug(&self) {
+        let mut state = self.lock();
+        state.usb_strings = usb_strings(&state.eeprom);
+    }
+
+    fn generate(&self, len: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; len];
+        let pause = {
+            let mut state = self.lock();
+            state.fill(&mut buf);
+            if state.realtime {
+                Some(Duration::from_secs_f64(len as f64 / 2.0 / state.real_rate))
+            } else {
+                None
+            }
+        };
+        if let Some(pause) = pause {
+            thread::sleep(pause);
+        }
+        buf
+    }
+}
+
+impl Default for SimDevice {
+    fn default() -> SimDevice {
+        SimDevice::new()
+    }
+}
+
+// The USB descriptors a dongle enumerates with, taken from its EEPROM.
+fn usb_strings(eeprom: &[u8]) -> (String, String, String) {
+    match EepromImage::parse(eeprom).and_then(|image| image.hw_info()) {
+        Ok(info) => (info.manufact, info.product, info.serial),
+        Err(_) => (String::new(), String::new(), String::new()),
+    }
+}
+
+impl SdrSource for SimDevice {
+    fn set_xtal_freq(&self, rtl_freq: Hertz, tuner_freq: Hertz) -> Result<()> {
+        let op = "rtlsdr_set_xtal_freq";
+        let (rtl_freq, tuner_freq) = (rtl_freq.hz(), tuner_freq.hz());
+        let nominal = CRYSTAL_FREQ as u32;
+        if rtl_freq > 0 &&
+           (rtl_freq < nominal - XTAL_TOLERANCE || rtl_freq > nominal + XTAL_TOLERANCE) {
+            return Err(invalid(op));
+        }
+        let mut state = self.lock();
+        if rtl_freq > 0 {
+            state.rtl_xtal = rtl_freq;
+            if state.rate > 0 {
+                let rate = SampleRate::new(state.rate);
+                state.real_rate = exact_sample_rate(Hertz::new(rtl_freq), rate);
+                state.rate = state.real_rate as u32;
+            }
+        }
+        if tuner_freq > 0 {
+            state.tuner_xtal = tuner_freq;
+        }
+        Ok(())
+    }
+
+    fn get_xtal_freq(&self) -> Result<(Hertz, Hertz)> {
+        let state = self.lock();
+        let corr = 1.0 + state.ppm as f64 / 1e6;
+        Ok((Hertz::new((state.rtl_xtal as f64 * corr) as u32),
+            Hertz::new((state.tuner_xtal as f64 * corr) as u32)))
+    }
+
+    fn get_usb_strings(&self) -> Result<(String, String, String)> {
+        Ok(self.lock().usb_strings.clone())
+    }
+
+    fn write_eeprom(&self, data: Vec<u8>, offset: u8) -> Result<()> {
+        if data.len() + offset as usize > EEPROM_SIZE as usize {
+            return Err(invalid("rtlsdr_write_eeprom"));
+        }
+        let offset = offset as usize;
+        self.lock().eeprom[offset..offset + data.len()].copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn read_eeprom(&self, offset: u8, len: u16) -> Result<Vec<u8>> {
+        let (offset, len) = (offset as usize, len as usize);
+        if offset + len > EEPROM_SIZE as usize {
+            return Err(invalid("rtlsdr_read_eeprom"));
+        }
+        Ok(self.lock().eeprom[offset..offset + len].to_vec())
+    }
+
+    fn set_center_freq(&self, freq: Hertz) -> Result<()> {
+        let freq = freq.hz();
+        let mut state = self.lock();
+        let ok = if state.direct_sampling != SamplingMode::None {
+            freq <= state.rtl_xtal
+        } else {
+            state.tuner.freq_table().iter().any(|&(lo, hi)| freq >= lo && freq <= hi)
+        };
+        if !ok {
+            return Err(Error::new("rtlsdr_set_center_freq", -1));
+        }
+        state.freq = freq;
+        Ok(())
+    }
+
+    fn get_center_freq(&self) -> Hertz {
+        Hertz::new(self.lock().freq)
+    }
+
+    fn set_freq_correction(&self, ppm: Ppm) -> Result<()> {
+        let ppm = ppm.ppm();
+        let mut state = self.lock();
+        // librtlsdr refuses to "change" to the current value.
+        if state.ppm == ppm {
+            return Err(invalid("rtlsdr_set_freq_correction"));
+        }
+        state.ppm = ppm;
+        Ok(())
+    }
+
+    fn get_freq_correction(&self) -> Ppm {
+        Ppm::new(self.lock().ppm)
+    }
+
+    fn get_tuner_type(&self) -> TunerType {
+        self.lock().tuner
+    }
+
+    fn get_tuner_gains(&self) -> Result<Vec<TenthDb>> {
+        let state = self.lock();
+        if state.tuner == TunerType::Unknown {
+            return Err(Error::new("rtlsdr_get_tuner_gains", 0));
+        }
+        Ok(state.tuner.gain_table().iter().cloned().map(TenthDb::new).collect())
+    }
+
+    fn set_tuner_gain(&self, gain: TenthDb) -> Result<()> {
+        let gain = gain.tenths();
+        let mut state = self.lock();
+        // The tuner drivers pick the closest step they support.
+        let nearest = state.tuner
+            .gain_table()
+            .iter()
+            .cloned()
+            .min_by_key(|g| (g - gain).abs())
+            .unwrap_or(0);
+        state.gain = nearest;
+        Ok(())
+    }
+
+    fn get_tuner_gain(&self) -> TenthDb {
+        TenthDb::new(self.lock().gain)
+    }
+
+    fn set_tuner_bandwidth(&self, bw: Hertz) -> Result<()> {
+        self.lock().bandwidth = bw.hz();
+        Ok(())
+    }
+
+    fn set_tuner_if_gain(&self, stage: i32, gain: TenthDb) -> Result<()> {
+        if !(1..=6).contains(&stage) {
+            return Err(invalid("rtlsdr_set_tuner_if_gain"));
+        }
+        self.lock().if_gains[stage as usize - 1] = gain.tenths();
+        Ok(())
+    }
+
+    fn set_tuner_gain_mode(&self, manual_mode: bool) -> Result<()> {
+        self.lock().manual_gain = manual_mode;
+        Ok(())
+    }
+
+    fn get_tuner_gain_mode(&self) -> bool {
+        self.lock().manual_gain
+    }
+
+    fn set_sample_rate(&self, rate: SampleRate) -> Result<()> {
+        check_sample_rate(rate)?;
+        let mut state = self.lock();
+        state.real_rate = exact_sample_rate(Hertz::new(state.rtl_xtal), rate);
+        state.rate = state.real_rate as u32;
+        Ok(())
+    }
+
+    fn get_sample_rate(&self) -> SampleRate {
+        SampleRate::new(self.lock().rate)
+    }
+
+    fn get_exact_sample_rate(&self) -> Result<f64> {
+        Ok(self.lock().real_rate)
+    }
+
+    fn set_testmode(&self, test_mode: bool) -> Result<()> {
+        self.lock().testmode = test_mode;
+        Ok(())
+    }
+
+    fn set_agc_mode(&self, agc_mode: bool) -> Result<()> {
+        self.lock().agc = agc_mode;
+        Ok(())
+    }
+
+    fn set_direct_sampling(&self, mode: SamplingMode) -> Result<()> {
+        if mode == SamplingMode::Error {
+            return Err(invalid("rtlsdr_set_direct_sampling"));
+        }
+        self.lock().direct_sampling = mode;
+        Ok(())
+    }
+
+    fn get_direct_sampling(&self) -> SamplingMode {
+        self.lock().direct_sampling
+    }
+
+    fn set_offset_tuning(&self, enable: bool) -> Result<()> {
+        let mut state = self.lock();
+        if state.tuner == TunerType::R820T || state.tuner == TunerType::R828D {
+            return Err(invalid("rtlsdr_set_offset_tuning"));
+        }
+        state.offset_tuning = enable;
+        Ok(())
+    }
+
+    fn get_offset_tuning(&self) -> Result<bool> {
+        Ok(self.lock().offset_tuning)
+    }
+
+    fn set_bias_tee_gpio(&self, gpio: u8, on: bool) -> Result<()> {
+        if gpio > MAX_GPIO {
+            return Err(invalid("rtlsdr_set_bias_tee_gpio"));
+        }
+        let mut state = self.lock();
+        state.bias_tees = if on {
+            state.bias_tees | 1 << gpio
+        } else {
+            state.bias_tees & !(1 << gpio)
+        };
+        Ok(())
+    }
+
+    fn get_bias_tee_gpio(&self, gpio: u8) -> bool {
+        gpio <= MAX_GPIO && self.lock().bias_tees & (1 << gpio) != 0
+    }
+
+    fn reset_buffer(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_sync(&self, len: i32) -> Result<Vec<u8>> {
+        if len < 0 {
+            return Err(invalid("rtlsdr_read_sync"));
+        }
+        Ok(self.generate(len as usize))
+    }
+
+    fn read_async_until(&self,
+                        _buf_num: i32,
+                        buf_len: i32,
+                        f: &mut dyn FnMut(&[u8]) -> AsyncControl)
+                        -> Result<()> {
+        let _running = Running::start(&self.running, &self.cancel)?;
+        let buf_len = if buf_len > 0 && buf_len % 512 == 0 {
+            buf_len as usize
+        } else {
+            DEFAULT_BUF_LENGTH as usize
+        };
+        while !self.cancel.load(Ordering::SeqCst) {
+            let buf = self.generate(buf_len);
+            if f(&buf) == AsyncControl::Stop {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn cancel_async(&self) -> Result<()> {
+        self.canceller().cancel_async()
+    }
+
+    fn canceller(&self) -> Canceller {
+        let running = self.running.clone();
+        let cancel = self.cancel.clone();
+        Canceller::new(move || {
+            if !running.load(Ordering::SeqCst) {
+                return Err(invalid("rtlsdr_cancel_async"));
+            }
+            cancel.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+}
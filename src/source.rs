@@ -0,0 +1,399 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::config;
+use super::eeprom;
+use super::resampler::nominal_xtal;
+use super::tuner;
+use super::{exact_sample_rate, nearest_exact_sample_rate, ApplyError, AsyncControl, Canceller,
+            Capabilities, Device, DeviceConfig, EepromImage, Error, ErrorKind, Gain, Hertz,
+            HwInfo, Ppm, Result, SampleRate, SamplingMode, TenthDb, TunerType};
+
+/// The device surface, so code written against it can run on librtlsdr
+/// (`Device`) or on any other backend or test double.
+///
+/// Methods follow the `Device` methods of the same name, including units
+/// and error behaviour.
+pub trait SdrSource {
+    /// Sets the crystal oscillator frequencies.
+    fn set_xtal_freq(&self, rtl_freq: Hertz, tuner_freq: Hertz) -> Result<()>;
+
+    /// Returns the crystal oscillator frequencies (rtl_freq, tuner_freq).
+    fn get_xtal_freq(&self) -> Result<(Hertz, Hertz)>;
+
+    /// Returns the device information (manufact, product, serial).
+    fn get_usb_strings(&self) -> Result<(String, String, String)>;
+
+    /// Writes information data to the EEPROM.
+    fn write_eeprom(&self, data: Vec<u8>, offset: u8) -> Result<()>;
+
+    /// Returns information data read from the EEPROM.
+    fn read_eeprom(&self, offset: u8, len: u16) -> Result<Vec<u8>>;
+
+    /// Sets the center frequency.
+    fn set_center_freq(&self, freq: Hertz) -> Result<()>;
+
+    /// Returns the tuned frequency or zero on error.
+    fn get_center_freq(&self) -> Hertz;
+
+    /// Sets the frequency correction.
+    fn set_freq_correction(&self, ppm: Ppm) -> Result<()>;
+
+    /// Returns the frequency correction value.
+    fn get_freq_correction(&self) -> Ppm;
+
+    /// Returns the tuner type.
+    fn get_tuner_type(&self) -> TunerType;
+
+    /// Returns a list of supported tuner gains.
+    fn get_tuner_gains(&self) -> Result<Vec<TenthDb>>;
+
+    /// Sets the tuner gain, manual gain mode must be enabled.
+    fn set_tuner_gain(&self, gain: TenthDb) -> Result<()>;
+
+    /// Returns the tuner gain.
+    fn get_tuner_gain(&self) -> TenthDb;
+
+    /// Sets the device bandwidth.
+    fn set_tuner_bandwidth(&self, bw: Hertz) -> Result<()>;
+
+    /// Sets the intermediate frequency gain.
+    fn set_tuner_if_gain(&self, stage: i32, gain: TenthDb) -> Result<()>;
+
+    /// Sets the gain mode, automatic or manual.
+    fn set_tuner_gain_mode(&self, manual_mode: bool) -> Result<()>;
+
+    /// Returns true in manual gain mode, false with the tuner's AGC.
+    fn get_tuner_gain_mode(&self) -> bool;
+
+    /// Sets the sample rate.
+    fn set_sample_rate(&self, rate: SampleRate) -> Result<()>;
+
+    /// Returns the sample rate.
+    fn get_sample_rate(&self) -> SampleRate;
+
+    /// Sets device to test mode.
+    fn set_testmode(&self, test_mode: bool) -> Result<()>;
+
+    /// Sets the AGC mode.
+    fn set_agc_mode(&self, agc_mode: bool) -> Result<()>;
+
+    /// Sets the direct sampling mode.
+    fn set_direct_sampling(&self, mode: SamplingMode) -> Result<()>;
+
+    /// Returns the state of direct sampling mode.
+    fn get_direct_sampling(&self) -> SamplingMode;
+
+    /// Sets the offset tuning mode.
+    fn set_offset_tuning(&self, enable: bool) -> Result<()>;
+
+    /// Returns the offset tuning mode.
+    fn get_offset_tuning(&self) -> Result<bool>;
+
+    /// Resets the streaming buffer.
+    fn reset_buffer(&self) -> Result<()>;
+
+    /// Performs a synchronous read of up to `len` samples.
+    fn read_sync(&self, len: i32) -> Result<Vec<u8>>;
+
+    /// Reads samples asynchronously until canceled or `f` returns
+    /// `AsyncControl::Stop`.
+    fn read_async_until(&self,
+                        buf_num: i32,
+                        buf_len: i32,
+                        f: &mut dyn FnMut(&[u8]) -> AsyncControl)
+                        -> Result<()>;
+
+    /// Cancels all pending asynchronous operations.
+    fn cancel_async(&self) -> Result<()>;
+
+    /// Returns a handle that can cancel asynchronous reads from another
+    /// thread.
+    fn canceller(&self) -> Canceller;
+
+    /// Reads samples asynchronously until canceled.
+    fn read_async(&self, buf_num: i32, buf_len: i32, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.read_async_until(buf_num, buf_len, &mut |buf| {
+            f(buf);
+            AsyncControl::Continue
+        })
+    }
+
+    /// Reads the dongle's information from the EEPROM.
+    fn get_hw_info(&self) -> Result<HwInfo> {
+        eeprom::read_image(self)?.hw_info()
+    }
+
+    /// Write the dongle's information to the EEPROM, leaving the rest of it
+    /// as it is.
+    fn set_hw_info(&self, info: &HwInfo) -> Result<()> {
+        eeprom::set_hw_info(self, info)
+    }
+
+    /// Reads the full EEPROM.
+    fn read_eeprom_image(&self) -> Result<EepromImage> {
+        eeprom::read_image(self)
+    }
+
+    /// Writes a full EEPROM image.
+    fn write_eeprom_image(&self, image: &EepromImage) -> Result<()> {
+        eeprom::write_image(self, image)
+    }
+
+    /// Switches the bias tee on GPIO 0 on or off.
+    fn set_bias_tee(&self, on: bool) -> Result<()> {
+        self.set_bias_tee_gpio(0, on)
+    }
+
+    /// Returns whether the bias tee on GPIO 0 is on.
+    fn get_bias_tee(&self) -> bool {
+        self.get_bias_tee_gpio(0)
+    }
+
+    /// Switches the bias tee on `gpio` on or off, by default `NotSupported`.
+    fn set_bias_tee_gpio(&self, _gpio: u8, _on: bool) -> Result<()> {
+        Err(Error::with_kind("set_bias_tee_gpio", ErrorKind::NotSupported, -12))
+    }
+
+    /// Returns whether the bias tee on `gpio` is on, by default false.
+    fn get_bias_tee_gpio(&self, _gpio: u8) -> bool {
+        false
+    }
+
+    /// Returns what the source supports, by default that of its tuner.
+    fn capabilities(&self) -> Capabilities {
+        self.get_tuner_type().capabilities()
+    }
+
+    /// Sets the gain, snapping manual gains to the nearest supported step
+    /// and switching gain modes as needed. Returns the gain applied.
+    fn set_gain(&self, gain: Gain) -> Result<Gain> {
+        tuner::set_gain(self, gain)
+    }
+
+    /// Sets the manual gain step nearest to `db` and returns it.
+    fn set_gain_db(&self, db: f64) -> Result<TenthDb> {
+        tuner::set_manual_gain(self, Gain::Manual(TenthDb::from_db(db)?))
+    }
+
+    /// Returns the gain in use, `Gain::Auto` or `Gain::Manual`.
+    fn get_gain(&self) -> Gain {
+        tuner::get_gain(self)
+    }
+
+    /// Applies the settings in `config` in dependency order and returns the
+    /// settings read back afterwards, see `Device::apply`.
+    fn apply(&self, config: &DeviceConfig) -> std::result::Result<DeviceConfig, ApplyError> {
+        config::apply(self, config)
+    }
+
+    /// Returns the exact sample rate, by default the one the resampler
+    /// produces for get_sample_rate.
+    fn get_exact_sample_rate(&self) -> Result<f64> {
+        let (xtal, _) = self.get_xtal_freq()?;
+        let xtal = nominal_xtal(xtal, self.get_freq_correction());
+        Ok(exact_sample_rate(xtal, self.get_sample_rate()))
+    }
+
+    /// Sets the valid sample rate nearest to `rate` that the resampler
+    /// produces exactly and returns it.
+    fn set_exact_sample_rate(&self, rate: SampleRate) -> Result<SampleRate> {
+        let (xtal, _) = self.get_xtal_freq()?;
+        let xtal = nominal_xtal(xtal, self.get_freq_correction());
+        let rate = nearest_exact_sample_rate(xtal, rate)?;
+        self.set_sample_rate(rate)?;
+        Ok(rate)
+    }
+}
+
+impl SdrSource for Device {
+    fn set_xtal_freq(&self, rtl_freq: Hertz, tuner_freq: Hertz) -> Result<()> {
+        Device::set_xtal_freq(self, rtl_freq, tuner_freq)
+    }
+
+    fn get_xtal_freq(&self) -> Result<(Hertz, Hertz)> {
+        Device::get_xtal_freq(self)
+    }
+
+    fn get_usb_strings(&self) -> Result<(String, String, String)> {
+        Device::get_usb_strings(self)
+    }
+
+    fn write_eeprom(&self, data: Vec<u8>, offset: u8) -> Result<()> {
+        Device::write_eeprom(self, data, offset)
+    }
+
+    fn read_eeprom(&self, offset: u8, len: u16) -> Result<Vec<u8>> {
+        Device::read_eeprom(self, offset, len)
+    }
+
+    fn set_center_freq(&self, freq: Hertz) -> Result<()> {
+        Device::set_center_freq(self, freq)
+    }
+
+    fn get_center_freq(&self) -> Hertz {
+        Device::get_center_freq(self)
+    }
+
+    fn set_freq_correction(&self, ppm: Ppm) -> Result<()> {
+        Device::set_freq_correction(self, ppm)
+    }
+
+    fn get_freq_correction(&self) -> Ppm {
+        Device::get_freq_correction(self)
+    }
+
+    fn get_tuner_type(&self) -> TunerType {
+        Device::get_tuner_type(self)
+    }
+
+    fn get_tuner_gains(&self) -> Result<Vec<TenthDb>> {
+        Device::get_tuner_gains(self)
+    }
+
+    fn set_tuner_gain(&self, gain: TenthDb) -> Result<()> {
+        Device::set_tuner_gain(self, gain)
+    }
+
+    fn get_tuner_gain(&self) -> TenthDb {
+        Device::get_tuner_gain(self)
+    }
+
+    fn set_tuner_bandwidth(&self, bw: Hertz) -> Result<()> {
+        Device::set_tuner_bandwidth(self, bw)
+    }
+
+    fn set_tuner_if_gain(&self, stage: i32, gain: TenthDb) -> Result<()> {
+        Device::set_tuner_if_gain(self, stage, gain)
+    }
+
+    fn set_tuner_gain_mode(&self, manual_mode: bool) -> Result<()> {
+        Device::set_tuner_gain_mode(self, manual_mode)
+    }
+
+    fn get_tuner_gain_mode(&self) -> bool {
+        Device::get_tuner_gain_mode(self)
+    }
+
+    fn set_sample_rate(&self, rate: SampleRate) -> Result<()> {
+        Device::set_sample_rate(self, rate)
+    }
+
+    fn get_sample_rate(&self) -> SampleRate {
+        Device::get_sample_rate(self)
+    }
+
+    fn get_exact_sample_rate(&self) -> Result<f64> {
+        Device::get_exact_sample_rate(self)
+    }
+
+    fn set_exact_sample_rate(&self, rate: SampleRate) -> Result<SampleRate> {
+        Device::set_exact_sample_rate(self, rate)
+    }
+
+    fn set_testmode(&self, test_mode: bool) -> Result<()> {
+        Device::set_testmode(self, test_mode)
+    }
+
+    fn set_agc_mode(&self, agc_mode: bool) -> Result<()> {
+        Device::set_agc_mode(self, agc_mode)
+    }
+
+    fn set_direct_sampling(&self, mode: SamplingMode) -> Result<()> {
+        Device::set_direct_sampling(self, mode)
+    }
+
+    fn get_direct_sampling(&self) -> SamplingMode {
+        Device::get_direct_sampling(self)
+    }
+
+    fn set_offset_tuning(&self, enable: bool) -> Result<()> {
+        Device::set_offset_tuning(self, enable)
+    }
+
+    fn get_offset_tuning(&self) -> Result<bool> {
+        Device::get_offset_tuning(self)
+    }
+
+    fn set_bias_tee(&self, on: bool) -> Result<()> {
+        Device::set_bias_tee(self, on)
+    }
+
+    fn get_bias_tee(&self) -> bool {
+        Device::get_bias_tee(self)
+    }
+
+    fn set_bias_tee_gpio(&self, gpio: u8, on: bool) -> Result<()> {
+        Device::set_bias_tee_gpio(self, gpio, on)
+    }
+
+    fn get_bias_tee_gpio(&self, gpio: u8) -> bool {
+        Device::get_bias_tee_gpio(self, gpio)
+    }
+
+    fn reset_buffer(&self) -> Result<()> {
+        Device::reset_buffer(self)
+    }
+
+    fn read_sync(&self, len: i32) -> Result<Vec<u8>> {
+        Device::read_sync(self, len)
+    }
+
+    fn read_async_until(&self,
+                        buf_num: i32,
+                        buf_len: i32,
+                        f: &mut dyn FnMut(&[u8]) -> AsyncControl)
+                        -> Result<()> {
+        Device::read_async_until(self, buf_num, buf_len, f)
+    }
+
+    fn cancel_async(&self) -> Result<()> {
+        Device::cancel_async(self)
+    }
+
+    fn canceller(&self) -> Canceller {
+        Device::canceller(self)
+    }
+
+    fn get_hw_info(&self) -> Result<HwInfo> {
+        Device::get_hw_info(self)
+    }
+
+    fn set_hw_info(&self, info: &HwInfo) -> Result<()> {
+        Device::set_hw_info(self, info)
+    }
+}
+
+// Marks a backend's read_async_until running until dropped, so a panicking
+// callback doesn't leave the backend busy.
+pub(crate) struct Running<'a>(&'a AtomicBool);
+
+impl<'a> Running<'a> {
+    // Starts a read, fails with Busy if one is running. `cancel` is reset
+    // before the read shows as running, a cancel from then on stops it.
+    pub(crate) fn start(running: &'a AtomicBool, cancel: &AtomicBool) -> Result<Running<'a>> {
+        let busy = || Error::new("rtlsdr_read_async", -6);
+        if running.load(Ordering::SeqCst) {
+            return Err(busy());
+        }
+        cancel.store(false, Ordering::SeqCst);
+        if running.swap(true, Ordering::SeqCst) {
+            return Err(busy());
+        }
+        Ok(Running(running))
+    }
+}
+
+impl<'a> Drop for Running<'a> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::panic;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::task::{Context, Poll, Waker};
+
+use super::{AsyncControl, Canceller, Device, Result, SdrSource, DEFAULT_ASYNC_BUF_NUMBER,
+            DEFAULT_BUF_LENGTH};
+
+/// What the reader thread does when the stream's queue is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Overflow {
+    /// Wait for the consumer, stalling the USB transfers.
+    Block,
+    /// Discard the oldest queued buffer to make room.
+    DropOldest,
+    /// Discard the buffer that just arrived.
+    DropNewest,
+}
+
+/// Stream settings.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct StreamOptions {
+    /// read_async buffer count.
+    pub buf_num: i32,
+    /// read_async buffer length, must be multiple of 512.
+    pub buf_len: i32,
+    /// Number of buffers the queue holds before `overflow` applies.
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for StreamOptions {
+    fn default() -> StreamOptions {
+        StreamOptions {
+            buf_num: DEFAULT_ASYNC_BUF_NUMBER,
+            buf_len: DEFAULT_BUF_LENGTH,
+            capacity: DEFAULT_ASYNC_BUF_NUMBER as usize,
+            overflow: Overflow::Block,
+        }
+    }
+}
+
+/// A buffer of 8-bit IQ samples received from the device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Buffer {
+    data: Vec<u8>,
+    seq: u64,
+}
+
+impl Buffer {
+    /// Returns the buffer's sequence number, gaps mean buffers were dropped.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the samples.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+struct State {
+    queue: VecDeque<Result<Buffer>>,
+    // Set by the consumer side, the reader stops at the next buffer.
+    closed: bool,
+    // Set by the reader thread once read_async has returned.
+    finished: bool,
+    // Set by `wake`, a reader blocked on a full queue runs its idle work.
+    woken: bool,
+    dropped: u64,
+    seq: u64,
+    // Consumer task to wake, for async consumers.
+    waker: Option<Waker>,
+}
+
+// Bounded buffer queue between a read_async callback and its consumer.
+pub(crate) struct Queue {
+    state: Mutex<State>,
+    cond: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl Queue {
+    pub(crate) fn new(opts: &StreamOptions) -> Queue {
+        Queue {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                closed: false,
+                finished: false,
+                woken: false,
+                dropped: 0,
+                seq: 0,
+                waker: None,
+            }),
+            cond: Condvar::new(),
+            capacity: opts.capacity.max(1),
+            overflow: opts.overflow,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn notify(&self, state: &mut State) {
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.cond.notify_all();
+    }
+
+    /// Queues a copy of `buf`, returns `Stop` once the consumer has gone.
+    pub(crate) fn push(&self, buf: &[u8]) -> AsyncControl {
+        self.push_or_idle(buf, &mut || AsyncControl::Continue)
+    }
+
+    /// Like `push`, but runs `idle` whenever `wake` is called while waiting
+    /// for room in a full queue, and stops if it returns `Stop`.
+    pub(crate) fn push_or_idle(&self,
+                               buf: &[u8],
+                               idle: &mut dyn FnMut() -> AsyncControl)
+                               -> AsyncControl {
+        let mut state = self.lock();
+        let seq = state.seq;
+        state.seq += 1;
+        loop {
+            if state.closed {
+                return AsyncControl::Stop;
+            }
+            if state.queue.len() < self.capacity {
+                break;
+            }
+            match self.overflow {
+                Overflow::Block if state.woken => {
+                    state.woken = false;
+                    drop(state);
+                    if idle() == AsyncControl::Stop {
+                        return AsyncControl::Stop;
+                    }
+                    state = self.lock();
+                }
+                Overflow::Block => {
+                    state = self.cond.wait(state).unwrap_or_else(|e| e.into_inner());
+                }
+                Overflow::DropOldest => {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                }
+                Overflow::DropNewest => {
+                    state.dropped += 1;
+                    return AsyncControl::Continue;
+                }
+            }
+        }
+        state.queue.push_back(Ok(Buffer {
+            data: buf.to_vec(),
+            seq,
+        }));
+        self.notify(&mut state);
+        AsyncControl::Continue
+    }
+
+    /// Ends the stream, `res` is the outcome of the read.
+    pub(crate) fn finish(&self, res: Result<()>) {
+        let mut state = self.lock();
+        if let Err(e) = res {
+            state.queue.push_back(Err(e));
+        }
+        state.finished = true;
+        self.notify(&mut state);
+    }
+
+    /// Makes a reader waiting for room run its idle work.
+    pub(crate) fn wake(&self) {
+        let mut state = self.lock();
+        state.woken = true;
+        self.cond.notify_all();
+    }
+
+    pub(crate) fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        self.notify(&mut state);
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.lock().finished
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    pub(crate) fn recv(&self, deadline: Option<Instant>) -> Option<Result<Buffer>> {
+        let mut state = self.lock();
+        loop {
+            if let Some(item) = state.queue.pop_front() {
+                self.cond.notify_all();
+                return Some(item);
+            }
+            if state.finished {
+                return None;
+            }
+            state = match deadline {
+                None => self.cond.wait(state).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    self.cond
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+            };
+        }
+    }
+
+    pub(crate) fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<Result<Buffer>>> {
+        let mut state = self.lock();
+        if let Some(item) = state.queue.pop_front() {
+            self.cond.notify_all();
+            return Poll::Ready(Some(item));
+        }
+        if state.finished {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Samples streamed from a device by a background read_async thread.
+///
+/// Iterating blocks for the next buffer and ends once the read stops, a read
+/// error is yielded as the last item. Dropping the stream cancels the read,
+/// joins the thread and closes the device, use `stop` to get the device back.
+pub struct Stream<S: SdrSource + Send + 'static = Device> {
+    shared: Arc<Queue>,
+    canceller: Canceller,
+    thread: Option<JoinHandle<S>>,
+}
+
+impl Device {
+    /// Starts streaming samples on a background thread.
+    pub fn stream(self, opts: StreamOptions) -> Stream {
+        Stream::new(self, opts)
+    }
+}
+
+impl<S: SdrSource + Send + 'static> Stream<S> {
+    /// Starts streaming samples from `src` on a background thread.
+    pub fn new(src: S, opts: StreamOptions) -> Stream<S> {
+        let shared = Arc::new(Queue::new(&opts));
+        let canceller = src.canceller();
+        let reader = shared.clone();
+        let thread = thread::spawn(move || {
+            let res = src.reset_buffer().and_then(|_| {
+                src.read_async_until(opts.buf_num, opts.buf_len, &mut |buf| reader.push(buf))
+            });
+            reader.finish(res);
+            src
+        });
+
+        Stream {
+            shared,
+            canceller,
+            thread: Some(thread),
+        }
+    }
+
+    /// Blocks until the next buffer arrives, returns `None` once the stream
+    /// has ended.
+    pub fn recv(&self) -> Option<Result<Buffer>> {
+        self.shared.recv(None)
+    }
+
+    /// Returns a queued buffer without blocking.
+    pub fn try_recv(&self) -> Option<Result<Buffer>> {
+        self.shared.recv(Some(Instant::now()))
+    }
+
+    /// Waits up to `timeout` for the next buffer.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Result<Buffer>> {
+        self.shared.recv(Some(Instant::now() + timeout))
+    }
+
+    /// Returns the number of buffers discarded by the overflow policy.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped()
+    }
+
+    /// Cancels the read, joins the reader thread and returns the device.
+    pub fn stop(mut self) -> S {
+        match self.shutdown() {
+            Some(Ok(dev)) => dev,
+            Some(Err(p)) => panic::resume_unwind(p),
+            None => unreachable!(),
+        }
+    }
+
+    fn shutdown(&mut self) -> Option<thread::Result<S>> {
+        let thread = self.thread.take()?;
+        self.shared.close();
+        // The reader also checks `closed` on every buffer, in case it hadn't
+        // reached read_async yet when this cancel was sent.
+        let _ = self.canceller.cancel_async();
+        Some(thread.join())
+    }
+}
+
+impl<S: SdrSource + Send + 'static> Iterator for Stream<S> {
+    type Item = Result<Buffer>;
+
+    fn next(&mut self) -> Option<Result<Buffer>> {
+        self.recv()
+    }
+}
+
+impl<S: SdrSource + Send + 'static> Drop for Stream<S> {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
@@ -0,0 +1,562 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! rtl_tcp network backend.
+//!
+//! An rtl_tcp server greets each client with a 12 byte header, "RTL0"
+//! followed by the big-endian tuner type and tuner gain count, then streams
+//! raw 8-bit IQ. Clients control the dongle with 5 byte packets, a command
+//! byte and a big-endian 32-bit parameter. The server never answers, so the
+//! getters return what was last sent.
+//!
+//! `TcpSource` is the client side, `Server` serves any `SdrSource` to
+//! rtl_tcp clients.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use super::source::Running;
+use super::stream::Queue;
+use super::{check_sample_rate, AsyncControl, Canceller, Device, Error, Hertz, Ppm, Result,
+            SampleRate, SamplingMode, SdrSource, StreamOptions, TenthDb, TunerType,
+            CRYSTAL_FREQ, DEFAULT_BUF_LENGTH, MIN_BUF_LENGTH};
+
+/// The rtl_tcp dongle info header magic.
+pub const MAGIC: &[u8; 4] = b"RTL0";
+
+// rtl_tcp command bytes.
+pub(crate) const SET_FREQ: u8 = 0x01;
+pub(crate) const SET_SAMPLE_RATE: u8 = 0x02;
+pub(crate) const SET_GAIN_MODE: u8 = 0x03;
+pub(crate) const SET_GAIN: u8 = 0x04;
+pub(crate) const SET_FREQ_CORRECTION: u8 = 0x05;
+pub(crate) const SET_IF_GAIN: u8 = 0x06;
+pub(crate) const SET_TEST_MODE: u8 = 0x07;
+pub(crate) const SET_AGC_MODE: u8 = 0x08;
+pub(crate) const SET_DIRECT_SAMPLING: u8 = 0x09;
+pub(crate) const SET_OFFSET_TUNING: u8 = 0x0a;
+pub(crate) const SET_RTL_XTAL: u8 = 0x0b;
+pub(crate) const SET_TUNER_XTAL: u8 = 0x0c;
+pub(crate) const SET_GAIN_BY_INDEX: u8 = 0x0d;
+pub(crate) const SET_BIAS_TEE: u8 = 0x0e;
+
+// How often a blocked read checks for cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Settings {
+    freq: Hertz,
+    rate: SampleRate,
+    ppm: Ppm,
+    gain: TenthDb,
+    manual_gain: bool,
+    rtl_xtal: Hertz,
+    tuner_xtal: Hertz,
+    direct_sampling: SamplingMode,
+    offset_tuning: bool,
+    bias_tee: bool,
+}
+
+/// A dongle behind an rtl_tcp server, see the module documentation.
+pub struct TcpSource {
+    reader: Mutex<TcpStream>,
+    writer: Mutex<TcpStream>,
+    tuner: TunerType,
+    gain_count: u32,
+    settings: Mutex<Settings>,
+    running: Arc<AtomicBool>,
+    cancel: Arc<AtomicBool>,
+}
+
+fn not_supported(op: &'static str) -> Error {
+    Error::new(op, -12)
+}
+
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl TcpSource {
+    /// Connects to an rtl_tcp server and reads its dongle info header.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<TcpSource> {
+        let op = "TcpSource::connect";
+        let stream = TcpStream::connect(addr).map_err(|e| Error::from_io(op, &e))?;
+        stream.set_nodelay(true).map_err(|e| Error::from_io(op, &e))?;
+        let mut header = [0u8; 12];
+        (&stream).read_exact(&mut header).map_err(|e| Error::from_io(op, &e))?;
+        if &header[..4] != MAGIC {
+            return Err(Error::new(op, -1));
+        }
+        let tuner = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let gain_count = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        stream.set_read_timeout(Some(POLL_INTERVAL)).map_err(|e| Error::from_io(op, &e))?;
+        let writer = stream.try_clone().map_err(|e| Error::from_io(op, &e))?;
+        Ok(TcpSource {
+            reader: Mutex::new(stream),
+            writer: Mutex::new(writer),
+            tuner: TunerType::from_raw(tuner),
+            gain_count,
+            settings: Mutex::new(Settings {
+                freq: Hertz::default(),
+                rate: SampleRate::default(),
+                ppm: Ppm::default(),
+                gain: TenthDb::default(),
+                manual_gain: false,
+                rtl_xtal: Hertz::new(CRYSTAL_FREQ as u32),
+                tuner_xtal: Hertz::new(CRYSTAL_FREQ as u32),
+                direct_sampling: SamplingMode::None,
+                offset_tuning: false,
+                bias_tee: false,
+            }),
+            running: Arc::new(AtomicBool::new(false)),
+            cancel: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Returns the number of tuner gains the server reported.
+    pub fn gain_count(&self) -> u32 {
+        self.gain_count
+    }
+
+    fn send(&self, op: &'static str, cmd: u8, param: u32) -> Result<()> {
+        let mut packet = [cmd, 0, 0, 0, 0];
+        packet[1..].copy_from_slice(&param.to_be_bytes());
+        lock(&self.writer).write_all(&packet).map_err(|e| Error::from_io(op, &e))
+    }
+
+    /// Sets the tuner gain by its index in the gain list.
+    pub fn set_tuner_gain_by_index(&self, index: u32) -> Result<()> {
+        self.send("rtl_tcp set_gain_by_index", SET_GAIN_BY_INDEX, index)
+    }
+
+    /// Turns the bias tee on or off.
+    pub fn set_bias_tee(&self, on: bool) -> Result<()> {
+        self.send("rtl_tcp set_bias_tee", SET_BIAS_TEE, on as u32)?;
+        lock(&self.settings).bias_tee = on;
+        Ok(())
+    }
+
+    /// Returns the last bias tee state sent.
+    pub fn get_bias_tee(&self) -> bool {
+        lock(&self.settings).bias_tee
+    }
+
+    /// Closes the connection.
+    pub fn close(self) -> Result<()> {
+        lock(&self.writer)
+            .shutdown(Shutdown::Both)
+            .map_err(|e| Error::from_io("TcpSource::close", &e))
+    }
+
+    // Fills `buf` from the socket, giving up early only when canceled before
+    // any of it arrived. A cancel doesn't cut a buffer short, the samples
+    // left of it would shift every later read off its I/Q boundary.
+    fn fill(&self, op: &'static str, buf: &mut [u8]) -> Result<usize> {
+        let mut reader = lock(&self.reader);
+        let mut n = 0;
+        while n < buf.len() {
+            match reader.read(&mut buf[n..]) {
+                Ok(0) => return Err(Error::from_io(op, &io::ErrorKind::UnexpectedEof.into())),
+                Ok(got) => n += got,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                              e.kind() == io::ErrorKind::TimedOut ||
+                              e.kind() == io::ErrorKind::Interrupted => {
+                    if n == 0 && self.cancel.load(Ordering::SeqCst) {
+                        break;
+                    }
+                }
+                Err(e) => return Err(Error::from_io(op, &e)),
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl SdrSource for TcpSource {
+    fn set_xtal_freq(&self, rtl_freq: Hertz, tuner_freq: Hertz) -> Result<()> {
+        if rtl_freq.hz() > 0 {
+            self.send("rtl_tcp set_rtl_xtal", SET_RTL_XTAL, rtl_freq.hz())?;
+            lock(&self.settings).rtl_xtal = rtl_freq;
+        }
+        if tuner_freq.hz() > 0 {
+            self.send("rtl_tcp set_tuner_xtal", SET_TUNER_XTAL, tuner_freq.hz())?;
+            lock(&self.settings).tuner_xtal = tuner_freq;
+        }
+        Ok(())
+    }
+
+    /// Corrected by the ppm error, like librtlsdr reports them.
+    fn get_xtal_freq(&self) -> Result<(Hertz, Hertz)> {
+        let settings = lock(&self.settings);
+        let corr = 1.0 + settings.ppm.ppm() as f64 / 1e6;
+        Ok((Hertz::new((settings.rtl_xtal.as_f64() * corr) as u32),
+            Hertz::new((settings.tuner_xtal.as_f64() * corr) as u32)))
+    }
+
+    fn get_usb_strings(&self) -> Result<(String, String, String)> {
+        Err(not_supported("rtlsdr_get_usb_strings"))
+    }
+
+    fn write_eeprom(&self, _data: Vec<u8>, _offset: u8) -> Result<()> {
+        Err(not_supported("rtlsdr_write_eeprom"))
+    }
+
+    fn read_eeprom(&self, _offset: u8, _len: u16) -> Result<Vec<u8>> {
+        Err(not_supported("rtlsdr_read_eeprom"))
+    }
+
+    fn set_center_freq(&self, freq: Hertz) -> Result<()> {
+        self.send("rtl_tcp set_freq", SET_FREQ, freq.hz())?;
+        lock(&self.settings).freq = freq;
+        Ok(())
+    }
+
+    fn get_center_freq(&self) -> Hertz {
+        lock(&self.settings).freq
+    }
+
+    fn set_freq_correction(&self, ppm: Ppm) -> Result<()> {
+        self.send("rtl_tcp set_freq_correction", SET_FREQ_CORRECTION, ppm.ppm() as u32)?;
+        lock(&self.settings).ppm = ppm;
+        Ok(())
+    }
+
+    fn get_freq_correction(&self) -> Ppm {
+        lock(&self.settings).ppm
+    }
+
+    fn get_tuner_type(&self) -> TunerType {
+        self.tuner
+    }
+
+    /// The server only reports the number of gains, the list is that of the
+    /// tuner type when the count matches.
+    fn get_tuner_gains(&self) -> Result<Vec<TenthDb>> {
+        let gains = self.tuner.gain_table();
+        if self.tuner == TunerType::Unknown || gains.len() != self.gain_count as usize {
+            return Err(not_supported("rtlsdr_get_tuner_gains"));
+        }
+        Ok(gains.iter().cloned().map(TenthDb::new).collect())
+    }
+
+    fn set_tuner_gain(&self, gain: TenthDb) -> Result<()> {
+        self.send("rtl_tcp set_gain", SET_GAIN, gain.tenths() as u32)?;
+        lock(&self.settings).gain = gain;
+        Ok(())
+    }
+
+    fn get_tuner_gain(&self) -> TenthDb {
+        lock(&self.settings).gain
+    }
+
+    fn set_tuner_bandwidth(&self, _bw: Hertz) -> Result<()> {
+        Err(not_supported("rtlsdr_set_tuner_bandwidth"))
+    }
+
+    fn set_tuner_if_gain(&self, stage: i32, gain: TenthDb) -> Result<()> {
+        let param = (stage as u32) << 16 | (gain.tenths() as u32 & 0xffff);
+        self.send("rtl_tcp set_if_gain", SET_IF_GAIN, param)
+    }
+
+    fn set_tuner_gain_mode(&self, manual_mode: bool) -> Result<()> {
+        self.send("rtl_tcp set_gain_mode", SET_GAIN_MODE, manual_mode as u32)?;
+        lock(&self.settings).manual_gain = manual_mode;
+        Ok(())
+    }
+
+    fn get_tuner_gain_mode(&self) -> bool {
+        lock(&self.settings).manual_gain
+    }
+
+    fn set_sample_rate(&self, rate: SampleRate) -> Result<()> {
+        // The server can't report errors, catch what librtlsdr would reject.
+        check_sample_rate(rate)?;
+        self.send("rtl_tcp set_sample_rate", SET_SAMPLE_RATE, rate.sps())?;
+        lock(&self.settings).rate = rate;
+        Ok(())
+    }
+
+    fn get_sample_rate(&self) -> SampleRate {
+        lock(&self.settings).rate
+    }
+
+    fn set_testmode(&self, test_mode: bool) -> Result<()> {
+        self.send("rtl_tcp set_testmode", SET_TEST_MODE, test_mode as u32)
+    }
+
+    fn set_agc_mode(&self, agc_mode: bool) -> Result<()> {
+        self.send("rtl_tcp set_agc_mode", SET_AGC_MODE, agc_mode as u32)
+    }
+
+    fn set_direct_sampling(&self, mode: SamplingMode) -> Result<()> {
+        let param = match mode {
+            SamplingMode::None => 0,
+            SamplingMode::IADC => 1,
+            SamplingMode::QADC => 2,
+            SamplingMode::Error => return Err(Error::new("rtl_tcp set_direct_sampling", -2)),
+        };
+        self.send("rtl_tcp set_direct_sampling", SET_DIRECT_SAMPLING, param)?;
+        lock(&self.settings).direct_sampling = mode;
+        Ok(())
+    }
+
+    fn get_direct_sampling(&self) -> SamplingMode {
+        lock(&self.settings).direct_sampling
+    }
+
+    fn set_offset_tuning(&self, enable: bool) -> Result<()> {
+        self.send("rtl_tcp set_offset_tuning", SET_OFFSET_TUNING, enable as u32)?;
+        lock(&self.settings).offset_tuning = enable;
+        Ok(())
+    }
+
+    fn get_offset_tuning(&self) -> Result<bool> {
+        Ok(lock(&self.settings).offset_tuning)
+    }
+
+    /// rtl_tcp only switches the bias tee on GPIO 0.
+    fn set_bias_tee_gpio(&self, gpio: u8, on: bool) -> Result<()> {
+        if gpio != 0 {
+            return Err(not_supported("rtl_tcp set_bias_tee"));
+        }
+        TcpSource::set_bias_tee(self, on)
+    }
+
+    fn get_bias_tee_gpio(&self, gpio: u8) -> bool {
+        gpio == 0 && TcpSource::get_bias_tee(self)
+    }
+
+    /// The server streams continuously, there is nothing to reset.
+    fn reset_buffer(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn read_sync(&self, len: i32) -> Result<Vec<u8>> {
+        if len < 0 {
+            return Err(Error::new("rtlsdr_read_sync", -2));
+        }
+        // The async read owns the socket and its cancel until it returns.
+        if self.running.load(Ordering::SeqCst) {
+            return Err(Error::new("rtlsdr_read_sync", -6));
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.cancel.store(false, Ordering::SeqCst);
+        let n = self.fill("rtlsdr_read_sync", &mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    fn read_async_until(&self,
+                        _buf_num: i32,
+                        buf_len: i32,
+                        f: &mut dyn FnMut(&[u8]) -> AsyncControl)
+                        -> Result<()> {
+        let _running = Running::start(&self.running, &self.cancel)?;
+        let buf_len = if buf_len > 0 && buf_len % MIN_BUF_LENGTH == 0 {
+            buf_len as usize
+        } else {
+            DEFAULT_BUF_LENGTH as usize
+        };
+        let mut buf = vec![0u8; buf_len];
+        loop {
+            match self.fill("rtlsdr_read_async", &mut buf) {
+                Ok(n) if n < buf_len => break Ok(()),
+                Ok(_) => {
+                    if f(&buf) == AsyncControl::Stop || self.cancel.load(Ordering::SeqCst) {
+                        break Ok(());
+                    }
+                }
+                Err(e) => break Err(e),
+            }
+        }
+    }
+
+    fn cancel_async(&self) -> Result<()> {
+        self.canceller().cancel_async()
+    }
+
+    fn canceller(&self) -> Canceller {
+        let running = self.running.clone();
+        let cancel = self.cancel.clone();
+        Canceller::new(move || {
+            if !running.load(Ordering::SeqCst) {
+                return Err(Error::new("rtlsdr_cancel_async", -2));
+            }
+            cancel.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+}
+
+/// Server settings.
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ServerOptions {
+    /// read_async buffers and the queue between the device and the client,
+    /// `overflow` decides what happens when the client falls behind.
+    pub stream: StreamOptions,
+    /// Disconnect a client once this many buffers were dropped for it.
+    pub max_dropped: Option<u64>,
+}
+
+/// An rtl_tcp server, see the module documentation.
+///
+/// Clients are served one at a time. Each gets the dongle info header and a
+/// stream of read_async buffers, its commands are applied between buffers.
+/// Failed commands are ignored, as rtl_tcp clients can't be told about them.
+pub struct Server<S: SdrSource = Device> {
+    listener: TcpListener,
+    dev: S,
+    opts: ServerOptions,
+}
+
+fn apply<S: SdrSource>(dev: &S, cmd: u8, param: u32) -> Result<()> {
+    match cmd {
+        SET_FREQ => dev.set_center_freq(Hertz::new(param)),
+        SET_SAMPLE_RATE => dev.set_sample_rate(SampleRate::new(param)),
+        SET_GAIN_MODE => dev.set_tuner_gain_mode(param != 0),
+        SET_GAIN => dev.set_tuner_gain(TenthDb::new(param as i32)),
+        SET_FREQ_CORRECTION => dev.set_freq_correction(Ppm::new(param as i32)),
+        SET_IF_GAIN => {
+            dev.set_tuner_if_gain((param >> 16) as i32, TenthDb::new(param as i16 as i32))
+        }
+        SET_TEST_MODE => dev.set_testmode(param != 0),
+        SET_AGC_MODE => dev.set_agc_mode(param != 0),
+        SET_DIRECT_SAMPLING => {
+            dev.set_direct_sampling(match param {
+                0 => SamplingMode::None,
+                1 => SamplingMode::IADC,
+                2 => SamplingMode::QADC,
+                _ => SamplingMode::Error,
+            })
+        }
+        SET_OFFSET_TUNING => dev.set_offset_tuning(param != 0),
+        SET_BIAS_TEE => dev.set_bias_tee(param != 0),
+        SET_RTL_XTAL => dev.set_xtal_freq(Hertz::new(param), Hertz::new(0)),
+        SET_TUNER_XTAL => dev.set_xtal_freq(Hertz::new(0), Hertz::new(param)),
+        SET_GAIN_BY_INDEX => {
+            let gains = dev.get_tuner_gains()?;
+            match gains.get(param as usize) {
+                Some(&gain) => dev.set_tuner_gain(gain),
+                None => Err(Error::new("rtl_tcp set_gain_by_index", -2)),
+            }
+        }
+        _ => Err(not_supported("rtl_tcp")),
+    }
+}
+
+impl<S: SdrSource> Server<S> {
+    /// Listens on `addr`, serving `dev`.
+    pub fn bind<A: ToSocketAddrs>(addr: A, dev: S, opts: ServerOptions) -> Result<Server<S>> {
+        let listener = TcpListener::bind(addr).map_err(|e| Error::from_io("Server::bind", &e))?;
+        Ok(Server { listener, dev, opts })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr().map_err(|e| Error::from_io("Server::local_addr", &e))
+    }
+
+    /// Returns the device being served.
+    pub fn device(&self) -> &S {
+        &self.dev
+    }
+
+    /// Stops serving and returns the device.
+    pub fn into_device(self) -> S {
+        self.dev
+    }
+
+    /// Serves clients until accepting one fails.
+    pub fn run(&self) -> Result<()> {
+        loop {
+            self.serve_one()?;
+        }
+    }
+
+    /// Waits for a client and serves it until it disconnects. Errors talking
+    /// to the client end the session, only device and listener errors are
+    /// returned.
+    pub fn serve_one(&self) -> Result<()> {
+        let op = "Server::serve_one";
+        let (conn, _) = self.listener.accept().map_err(|e| Error::from_io(op, &e))?;
+        let _ = conn.set_nodelay(true);
+
+        let gains = self.dev.get_tuner_gains().map(|g| g.len()).unwrap_or(0);
+        let mut header = [0u8; 12];
+        header[..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&(self.dev.get_tuner_type() as u32).to_be_bytes());
+        header[8..].copy_from_slice(&(gains as u32).to_be_bytes());
+        if (&conn).write_all(&header).is_err() {
+            return Ok(());
+        }
+
+        let (cmd_conn, mut out) = match (conn.try_clone(), conn.try_clone()) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(e), _) | (_, Err(e)) => return Err(Error::from_io(op, &e)),
+        };
+
+        // Client commands, the channel disconnects when the client does.
+        let (tx, rx) = mpsc::channel();
+        let commands = thread::spawn(move || {
+            let mut cmd_conn = cmd_conn;
+            let mut packet = [0u8; 5];
+            while cmd_conn.read_exact(&mut packet).is_ok() {
+                let param = u32::from_be_bytes([packet[1], packet[2], packet[3], packet[4]]);
+                if tx.send((packet[0], param)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Samples out to the client.
+        let queue = Arc::new(Queue::new(&self.opts.stream));
+        let sender = queue.clone();
+        let samples = thread::spawn(move || {
+            while let Some(Ok(buf)) = sender.recv(None) {
+                if out.write_all(&buf).is_err() {
+                    break;
+                }
+            }
+            sender.close();
+        });
+
+        let max_dropped = self.opts.max_dropped;
+        let dev = &self.dev;
+        let res = dev.reset_buffer().and_then(|_| {
+            dev.read_async_until(self.opts.stream.buf_num, self.opts.stream.buf_len, &mut |buf| {
+                loop {
+                    match rx.try_recv() {
+                        Ok((cmd, param)) => {
+                            let _ = apply(dev, cmd, param);
+                        }
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return AsyncControl::Stop,
+                    }
+                }
+                if max_dropped.is_some_and(|max| queue.dropped() > max) {
+                    return AsyncControl::Stop;
+                }
+                queue.push(buf)
+            })
+        });
+
+        queue.finish(Ok(()));
+        queue.close();
+        let _ = conn.shutdown(Shutdown::Both);
+        let _ = samples.join();
+        let _ = commands.join();
+        res
+    }
+}
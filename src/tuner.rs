@@ -0,0 +1,232 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use super::resampler::SAMPLE_RATE_RANGES;
+use super::{Error, ErrorKind, Hertz, Result, SampleRate, SdrSource, TenthDb};
+
+/// Tuner chips, numbered as librtlsdr and rtl_tcp number them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum TunerType {
+    #[default]
+    Unknown = 0,
+    E4000 = 1,
+    FC0012 = 2,
+    FC0013 = 3,
+    FC2580 = 4,
+    R820T = 5,
+    R828D = 6,
+}
+
+/// What a dongle's tuner can do, see `SdrSource::capabilities`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    pub tuner: TunerType,
+    /// Tuning ranges, more than one when the tuner has a gap, e.g. the
+    /// E4000's 1100-1250 MHz hole.
+    pub freq_ranges: Vec<RangeInclusive<Hertz>>,
+    /// Gain steps for manual gain mode, empty when the gain is fixed.
+    pub gains: Vec<TenthDb>,
+    /// Number of IF gain stages for set_tuner_if_gain, numbered from 1.
+    pub if_gain_stages: u32,
+    pub offset_tuning: bool,
+    /// Whether set_tuner_bandwidth selects a filter, otherwise it's a no-op.
+    pub bandwidth: bool,
+    /// Whether direct sampling, tuning from 0 Hz up to the xtal frequency
+    /// on the I or Q ADC branch, is available.
+    pub direct_sampling: bool,
+    pub sample_rates: Vec<RangeInclusive<SampleRate>>,
+}
+
+/// A tuner gain setting, see `SdrSource::set_gain`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Gain {
+    /// The tuner's AGC.
+    Auto,
+    /// The lowest manual gain step.
+    Min,
+    /// The highest manual gain step.
+    Max,
+    /// The manual gain step nearest to this.
+    Manual(TenthDb),
+}
+
+// Gain steps in tenths of dB, as librtlsdr reports them.
+const R82XX_GAINS: &[i32] = &[0, 9, 14, 27, 37, 77, 87, 125, 144, 157, 166, 197, 207, 229, 254,
+                              280, 297, 328, 338, 364, 372, 386, 402, 421, 434, 439, 445, 480,
+                              496];
+const E4K_GAINS: &[i32] = &[-10, 15, 40, 65, 90, 115, 140, 165, 190, 215, 240, 290, 340, 420];
+const FC0012_GAINS: &[i32] = &[-99, -40, 71, 179, 192];
+const FC0013_GAINS: &[i32] = &[-99, -73, -65, -63, -60, -58, -54, 58, 61, 63, 65, 67, 68, 70,
+                               71, 179, 181, 182, 184, 186, 188, 191, 197];
+const NO_GAINS: &[i32] = &[0];
+
+// Tuning ranges in Hz.
+const R82XX_RANGES: &[(u32, u32)] = &[(24_000_000, 1_766_000_000)];
+const E4K_RANGES: &[(u32, u32)] = &[(52_000_000, 1_100_000_000),
+                                    (1_250_000_000, 2_200_000_000)];
+const FC0012_RANGES: &[(u32, u32)] = &[(22_000_000, 948_600_000)];
+const FC0013_RANGES: &[(u32, u32)] = &[(22_000_000, 1_100_000_000)];
+const FC2580_RANGES: &[(u32, u32)] = &[(146_000_000, 308_000_000), (438_000_000, 924_000_000)];
+
+const TUNERS: &[TunerType] = &[TunerType::Unknown,
+                               TunerType::E4000,
+                               TunerType::FC0012,
+                               TunerType::FC0013,
+                               TunerType::FC2580,
+                               TunerType::R820T,
+                               TunerType::R828D];
+
+impl TunerType {
+    /// Converts librtlsdr's and rtl_tcp's tuner number, anything out of
+    /// range is `Unknown`.
+    pub fn from_raw(raw: u32) -> TunerType {
+        TUNERS.get(raw as usize).cloned().unwrap_or(TunerType::Unknown)
+    }
+
+    /// Returns the tuner's name, e.g. "R820T".
+    pub fn name(self) -> &'static str {
+        match self {
+            TunerType::Unknown => "Unknown",
+            TunerType::E4000 => "E4000",
+            TunerType::FC0012 => "FC0012",
+            TunerType::FC0013 => "FC0013",
+            TunerType::FC2580 => "FC2580",
+            TunerType::R820T => "R820T",
+            TunerType::R828D => "R828D",
+        }
+    }
+
+    /// Gain steps in tenths of dB exactly as rtlsdr_get_tuner_gains lists
+    /// them, a single 0 for tuners without gain control.
+    pub(crate) fn gain_table(self) -> &'static [i32] {
+        match self {
+            TunerType::E4000 => E4K_GAINS,
+            TunerType::FC0012 => FC0012_GAINS,
+            TunerType::FC0013 => FC0013_GAINS,
+            TunerType::R820T | TunerType::R828D => R82XX_GAINS,
+            TunerType::FC2580 | TunerType::Unknown => NO_GAINS,
+        }
+    }
+
+    /// Tuning ranges in Hz.
+    pub(crate) fn freq_table(self) -> &'static [(u32, u32)] {
+        match self {
+            TunerType::E4000 => E4K_RANGES,
+            TunerType::FC0012 => FC0012_RANGES,
+            TunerType::FC0013 => FC0013_RANGES,
+            TunerType::FC2580 => FC2580_RANGES,
+            TunerType::R820T | TunerType::R828D => R82XX_RANGES,
+            TunerType::Unknown => &[],
+        }
+    }
+
+    /// Returns what a dongle with this tuner supports.
+    pub fn capabilities(self) -> Capabilities {
+        let gains = self.gain_table();
+        let is_r82xx = self == TunerType::R820T || self == TunerType::R828D;
+        Capabilities {
+            tuner: self,
+            freq_ranges: self.freq_table()
+                .iter()
+                .map(|&(lo, hi)| Hertz::new(lo)..=Hertz::new(hi))
+                .collect(),
+            gains: if gains.len() > 1 {
+                gains.iter().cloned().map(TenthDb::new).collect()
+            } else {
+                Vec::new()
+            },
+            if_gain_stages: if self == TunerType::E4000 { 6 } else { 0 },
+            // The R82xx drivers tune to a low IF already and refuse it.
+            offset_tuning: !is_r82xx && self != TunerType::Unknown,
+            bandwidth: match self {
+                TunerType::E4000 | TunerType::FC2580 => true,
+                _ => is_r82xx,
+            },
+            // Done by the RTL2832 itself, so it works whatever the tuner.
+            direct_sampling: true,
+            sample_rates: SAMPLE_RATE_RANGES.iter()
+                .map(|&(lo, hi)| SampleRate::new(lo)..=SampleRate::new(hi))
+                .collect(),
+        }
+    }
+}
+
+impl Capabilities {
+    /// Whether `freq` is within one of the tuning ranges.
+    pub fn tunes_to(&self, freq: Hertz) -> bool {
+        self.freq_ranges.iter().any(|r| r.contains(&freq))
+    }
+
+    /// Whether `rate` is within one of the sample rate ranges.
+    pub fn supports_sample_rate(&self, rate: SampleRate) -> bool {
+        self.sample_rates.iter().any(|r| r.contains(&rate))
+    }
+}
+
+impl fmt::Display for TunerType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TunerType {
+    type Err = Error;
+
+    /// Parses a tuner name as get_tuner_type displays it, ignoring case.
+    fn from_str(s: &str) -> Result<TunerType> {
+        TUNERS.iter()
+            .cloned()
+            .find(|t| t.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| Error::with_kind("TunerType::from_str", ErrorKind::InvalidParam, -2))
+    }
+}
+
+/// Applies `gain` to `dev`, switching gain modes as needed. Returns the gain
+/// in use afterwards.
+pub(crate) fn set_gain<S: SdrSource + ?Sized>(dev: &S, gain: Gain) -> Result<Gain> {
+    match gain {
+        Gain::Auto => {
+            dev.set_tuner_gain_mode(false)?;
+            Ok(Gain::Auto)
+        }
+        _ => set_manual_gain(dev, gain).map(Gain::Manual),
+    }
+}
+
+/// Sets the manual gain step `gain` picks, returns the one applied.
+pub(crate) fn set_manual_gain<S: SdrSource + ?Sized>(dev: &S, gain: Gain) -> Result<TenthDb> {
+    let gains = dev.get_tuner_gains()?;
+    let step = match gain {
+        Gain::Min => gains.iter().min(),
+        Gain::Max => gains.iter().max(),
+        Gain::Manual(target) => gains.iter().min_by_key(|g| (g.tenths() - target.tenths()).abs()),
+        Gain::Auto => None,
+    };
+    let step = *step.ok_or_else(|| Error::with_kind("set_gain", ErrorKind::NotSupported, -12))?;
+    dev.set_tuner_gain_mode(true)?;
+    dev.set_tuner_gain(step)?;
+    Ok(dev.get_tuner_gain())
+}
+
+/// Returns the gain in use.
+pub(crate) fn get_gain<S: SdrSource + ?Sized>(dev: &S) -> Gain {
+    if dev.get_tuner_gain_mode() {
+        Gain::Manual(dev.get_tuner_gain())
+    } else {
+        Gain::Auto
+    }
+}
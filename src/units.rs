@@ -0,0 +1,346 @@
+// Copyright (c) 2016 Joseph D Poirier <jdpoirier@gmail.com>
+// Licensed under the MIT License <LICENSE-MIT.md>
+
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use super::{Error, ErrorKind, Result};
+
+/// A frequency, librtlsdr takes them as unsigned 32-bit Hz.
+///
+/// Displays with the largest fitting unit, e.g. "978MHz", and parses plain Hz
+/// or k/M/G suffixed values with or without "Hz", e.g. "978M" or "1.09 GHz".
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Hertz(u32);
+
+/// A sample rate in samples per second.
+///
+/// Displays like "2.048Msps" and parses the same forms as `Hertz`, with an
+/// optional "sps" or "S/s" suffix, e.g. "2.048Msps" or "250k".
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SampleRate(u32);
+
+/// A frequency correction in parts per million, e.g. "-3ppm".
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Ppm(i32);
+
+/// A gain in tenths of dB, the unit librtlsdr uses, e.g. 496 is "49.6dB".
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct TenthDb(i32);
+
+fn invalid(op: &'static str) -> Error {
+    Error::with_kind(op, ErrorKind::InvalidParam, -2)
+}
+
+// Formats `v / scale` exactly, without trailing fraction zeros.
+fn fmt_scaled(f: &mut fmt::Formatter, v: u64, scale: u64, unit: &str) -> fmt::Result {
+    let frac = v % scale;
+    if frac == 0 {
+        return write!(f, "{}{}", v / scale, unit);
+    }
+    let width = (scale as f64).log10() as usize;
+    let digits = format!("{:0width$}", frac, width = width);
+    write!(f, "{}.{}{}", v / scale, digits.trim_end_matches('0'), unit)
+}
+
+fn fmt_si(f: &mut fmt::Formatter, v: u64, unit: &str) -> fmt::Result {
+    if v >= 1_000_000_000 {
+        fmt_scaled(f, v, 1_000_000_000, &format!("G{}", unit))
+    } else if v >= 1_000_000 {
+        fmt_scaled(f, v, 1_000_000, &format!("M{}", unit))
+    } else if v >= 1_000 {
+        fmt_scaled(f, v, 1_000, &format!("k{}", unit))
+    } else {
+        write!(f, "{}{}", v, unit)
+    }
+}
+
+// Splits "49.6 dB" into (49.6, "db").
+fn split_number(s: &str) -> Option<(f64, String)> {
+    let s = s.trim();
+    let end = s.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(s.len());
+    let value = s[..end].parse().ok()?;
+    Some((value, s[end..].trim().to_ascii_lowercase()))
+}
+
+// Parses an SI prefixed value, `units` are the accepted unit suffixes.
+fn parse_si(s: &str, units: &[&str]) -> Option<f64> {
+    let (value, suffix) = split_number(s)?;
+    let mut suffix = suffix.as_str();
+    if let Some(unit) = units.iter().find(|u| suffix.ends_with(*u)) {
+        suffix = &suffix[..suffix.len() - unit.len()];
+    }
+    let scale = match suffix {
+        "" => 1e0,
+        "k" => 1e3,
+        "m" => 1e6,
+        "g" => 1e9,
+        _ => return None,
+    };
+    Some(value * scale)
+}
+
+fn checked_u32(op: &'static str, v: f64) -> Result<u32> {
+    let v = v.round();
+    if v.is_finite() && v >= 0.0 && v <= u32::MAX as f64 {
+        Ok(v as u32)
+    } else {
+        Err(invalid(op))
+    }
+}
+
+impl Hertz {
+    /// Creates a frequency from Hz.
+    pub const fn new(hz: u32) -> Hertz {
+        Hertz(hz)
+    }
+
+    /// Creates a frequency from kHz, `None` if it doesn't fit.
+    pub fn from_khz(khz: u32) -> Option<Hertz> {
+        khz.checked_mul(1_000).map(Hertz)
+    }
+
+    /// Creates a frequency from MHz, `None` if it doesn't fit.
+    pub fn from_mhz(mhz: u32) -> Option<Hertz> {
+        mhz.checked_mul(1_000_000).map(Hertz)
+    }
+
+    /// Returns the frequency in Hz.
+    pub fn hz(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the frequency in Hz as a float.
+    pub fn as_f64(self) -> f64 {
+        self.0 as f64
+    }
+}
+
+impl SampleRate {
+    /// Creates a sample rate from samples per second.
+    pub const fn new(sps: u32) -> SampleRate {
+        SampleRate(sps)
+    }
+
+    /// Returns the rate in samples per second.
+    pub fn sps(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the rate in samples per second as a float.
+    pub fn as_f64(self) -> f64 {
+        self.0 as f64
+    }
+}
+
+impl Ppm {
+    /// Creates a frequency correction.
+    pub const fn new(ppm: i32) -> Ppm {
+        Ppm(ppm)
+    }
+
+    /// Returns the correction in ppm.
+    pub fn ppm(self) -> i32 {
+        self.0
+    }
+}
+
+impl TenthDb {
+    /// Creates a gain from tenths of dB.
+    pub const fn new(tenths: i32) -> TenthDb {
+        TenthDb(tenths)
+    }
+
+    /// Creates a gain from dB, rounded to the nearest tenth.
+    pub fn from_db(db: f64) -> Result<TenthDb> {
+        let tenths = (db * 10.0).round();
+        if tenths.is_finite() && tenths >= i32::MIN as f64 && tenths <= i32::MAX as f64 {
+            Ok(TenthDb(tenths as i32))
+        } else {
+            Err(invalid("TenthDb::from_db"))
+        }
+    }
+
+    /// Returns the gain in tenths of dB.
+    pub fn tenths(self) -> i32 {
+        self.0
+    }
+
+    /// Returns the gain in dB.
+    pub fn db(self) -> f64 {
+        self.0 as f64 / 10.0
+    }
+}
+
+impl From<u32> for Hertz {
+    fn from(hz: u32) -> Hertz {
+        Hertz(hz)
+    }
+}
+
+impl From<Hertz> for u32 {
+    fn from(f: Hertz) -> u32 {
+        f.0
+    }
+}
+
+impl From<Hertz> for u64 {
+    fn from(f: Hertz) -> u64 {
+        f.0 as u64
+    }
+}
+
+impl TryFrom<u64> for Hertz {
+    type Error = Error;
+
+    fn try_from(hz: u64) -> Result<Hertz> {
+        u32::try_from(hz).map(Hertz).map_err(|_| invalid("Hertz::try_from"))
+    }
+}
+
+impl TryFrom<i64> for Hertz {
+    type Error = Error;
+
+    fn try_from(hz: i64) -> Result<Hertz> {
+        u32::try_from(hz).map(Hertz).map_err(|_| invalid("Hertz::try_from"))
+    }
+}
+
+impl TryFrom<i32> for Hertz {
+    type Error = Error;
+
+    fn try_from(hz: i32) -> Result<Hertz> {
+        u32::try_from(hz).map(Hertz).map_err(|_| invalid("Hertz::try_from"))
+    }
+}
+
+impl TryFrom<f64> for Hertz {
+    type Error = Error;
+
+    fn try_from(hz: f64) -> Result<Hertz> {
+        checked_u32("Hertz::try_from", hz).map(Hertz)
+    }
+}
+
+impl From<u32> for SampleRate {
+    fn from(sps: u32) -> SampleRate {
+        SampleRate(sps)
+    }
+}
+
+impl From<SampleRate> for u32 {
+    fn from(r: SampleRate) -> u32 {
+        r.0
+    }
+}
+
+impl TryFrom<i32> for SampleRate {
+    type Error = Error;
+
+    fn try_from(sps: i32) -> Result<SampleRate> {
+        u32::try_from(sps).map(SampleRate).map_err(|_| invalid("SampleRate::try_from"))
+    }
+}
+
+impl TryFrom<f64> for SampleRate {
+    type Error = Error;
+
+    fn try_from(sps: f64) -> Result<SampleRate> {
+        checked_u32("SampleRate::try_from", sps).map(SampleRate)
+    }
+}
+
+impl From<i32> for Ppm {
+    fn from(ppm: i32) -> Ppm {
+        Ppm(ppm)
+    }
+}
+
+impl From<Ppm> for i32 {
+    fn from(p: Ppm) -> i32 {
+        p.0
+    }
+}
+
+impl fmt::Display for Hertz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_si(f, self.0 as u64, "Hz")
+    }
+}
+
+impl fmt::Display for SampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_si(f, self.0 as u64, "sps")
+    }
+}
+
+impl fmt::Display for Ppm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}ppm", self.0)
+    }
+}
+
+impl fmt::Display for TenthDb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 < 0 {
+            write!(f, "-")?;
+        }
+        fmt_scaled(f, self.0.unsigned_abs() as u64, 10, "dB")
+    }
+}
+
+impl FromStr for Hertz {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Hertz> {
+        let hz = parse_si(s, &["hz"]).ok_or_else(|| invalid("Hertz::from_str"))?;
+        checked_u32("Hertz::from_str", hz).map(Hertz)
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SampleRate> {
+        let op = "SampleRate::from_str";
+        let sps = parse_si(s, &["sps", "s/s", "hz"]).ok_or_else(|| invalid(op))?;
+        checked_u32(op, sps).map(SampleRate)
+    }
+}
+
+impl FromStr for Ppm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Ppm> {
+        let s = s.trim();
+        let s = s.strip_suffix("ppm").unwrap_or(s).trim_end();
+        s.parse().map(Ppm).map_err(|_| invalid("Ppm::from_str"))
+    }
+}
+
+impl FromStr for TenthDb {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<TenthDb> {
+        match split_number(s) {
+            Some((db, ref unit)) if unit.is_empty() || unit == "db" => TenthDb::from_db(db),
+            _ => Err(invalid("TenthDb::from_str")),
+        }
+    }
+}
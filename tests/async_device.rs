@@ -0,0 +1,100 @@
+#![cfg(feature = "async")]
+
+extern crate futures;
+extern crate rtlsdr;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use futures::executor::block_on;
+use futures::StreamExt;
+
+use rtlsdr::sim::SimDevice;
+use rtlsdr::{AsyncDevice, Hertz, Overflow, SdrSource, StreamOptions};
+
+// A small queue the simulated device fills at once.
+fn opts() -> StreamOptions {
+    StreamOptions {
+        buf_num: 4,
+        buf_len: 16_384,
+        capacity: 2,
+        overflow: Overflow::Block,
+    }
+}
+
+// Runs `f` on a thread, failing instead of hanging if it doesn't finish.
+fn within<F: FnOnce() + Send + 'static>(f: F) {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        f();
+        let _ = tx.send(());
+    });
+    rx.recv_timeout(Duration::from_secs(10)).expect("timed out");
+}
+
+#[test]
+fn control_calls_with_a_full_queue() {
+    within(|| {
+        let dev = AsyncDevice::new(SimDevice::new());
+        block_on(async {
+            let mut samples = dev.stream(opts());
+            let mut count = 0;
+            while let Some(buf) = samples.next().await {
+                assert_eq!(buf.unwrap().len(), 16_384);
+                // Let the worker block on the full queue, then control it
+                // without draining the stream.
+                thread::sleep(Duration::from_millis(50));
+                let freq = Hertz::new(100_000_000 + count);
+                dev.set_center_freq(freq).await.unwrap();
+                assert_eq!(dev.get_center_freq().await.unwrap(), freq);
+                count += 1;
+                if count == 5 {
+                    break;
+                }
+            }
+        });
+        assert_eq!(dev.into_device().get_center_freq(), Hertz::new(100_000_004));
+    });
+}
+
+#[test]
+fn dropping_the_stream_cancels_the_read() {
+    within(|| {
+        let dev = AsyncDevice::new(SimDevice::new());
+        block_on(async {
+            let mut samples = dev.stream(opts());
+            samples.next().await.unwrap().unwrap();
+            thread::sleep(Duration::from_millis(50));
+            drop(samples);
+            // The read has stopped, a new stream can start.
+            dev.set_center_freq(Hertz::new(978_000_000)).await.unwrap();
+            let mut samples = dev.stream(opts());
+            samples.next().await.unwrap().unwrap();
+        });
+        let sim = dev.into_device();
+        assert_eq!(sim.get_center_freq(), Hertz::new(978_000_000));
+    });
+}
+
+#[test]
+fn dropping_the_device_with_a_full_stream() {
+    within(|| {
+        let dev = AsyncDevice::new(SimDevice::new());
+        let mut samples = dev.stream(opts());
+        block_on(async {
+            samples.next().await.unwrap().unwrap();
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop(dev);
+        // What was queued is still there, then the stream ends.
+        block_on(async {
+            let mut left = 0;
+            while let Some(buf) = samples.next().await {
+                buf.unwrap();
+                left += 1;
+            }
+            assert!(left <= 2);
+        });
+    });
+}
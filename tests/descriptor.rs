@@ -0,0 +1,113 @@
+extern crate proptest;
+extern crate rtlsdr;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use rtlsdr::eeprom::{decode_descriptor, encode_descriptor, MAX_DESCRIPTOR_UNITS};
+use rtlsdr::sim::SimDevice;
+use rtlsdr::{EepromImage, ErrorKind, SdrSource};
+
+proptest! {
+    #[test]
+    fn decode_arbitrary_bytes(data in vec(any::<u8>(), 0..300)) {
+        match decode_descriptor(&data) {
+            Ok((s, len)) => {
+                prop_assert!(len >= 2 && len <= data.len() && len % 2 == 0);
+                prop_assert_eq!(encode_descriptor(&s).unwrap(), data[..len].to_vec());
+            }
+            Err(e) => prop_assert_eq!(e.kind(), ErrorKind::StringDescriptorInvalid),
+        }
+    }
+
+    #[test]
+    fn encode_round_trips(s in any::<String>()) {
+        let units = s.encode_utf16().count();
+        match encode_descriptor(&s) {
+            Ok(data) => {
+                prop_assert!(units <= MAX_DESCRIPTOR_UNITS);
+                prop_assert_eq!(data.len(), units * 2 + 2);
+                prop_assert_eq!(decode_descriptor(&data).unwrap(), (s, data.len()));
+                for cut in 0..data.len() {
+                    let e = decode_descriptor(&data[..cut]).unwrap_err();
+                    prop_assert_eq!(e.kind(), ErrorKind::StringDescriptorInvalid);
+                }
+            }
+            Err(e) => {
+                prop_assert!(units > MAX_DESCRIPTOR_UNITS);
+                prop_assert_eq!(e.kind(), ErrorKind::StringDescriptorTooLong);
+            }
+        }
+    }
+
+    #[test]
+    fn arbitrary_images_never_panic(data in vec(any::<u8>(), 256)) {
+        let image = EepromImage::parse(&data).unwrap();
+        let _ = image.hw_info();
+        let _ = (image.manufacturer(), image.product(), image.serial());
+    }
+
+    #[test]
+    fn descriptor_chains_never_panic(lens in vec(0u8..=255, 1..4), fill in any::<u8>()) {
+        // Descriptors with arbitrary lengths, often running to or past the end.
+        let mut data = vec![0x28, 0x32, 0xda, 0x0b, 0x38, 0x28, 0xa5, 0x14, 0x02];
+        for len in lens {
+            data.extend_from_slice(&[len, 0x03]);
+            data.resize(data.len() + (len as usize).saturating_sub(2), fill);
+        }
+        data.resize(256, fill);
+        let image = EepromImage::parse(&data).unwrap();
+        prop_assert_eq!(image.to_bytes().unwrap(), data);
+        let _ = image.hw_info();
+    }
+}
+
+#[test]
+fn strings_up_to_the_end() {
+    let mut data = vec![0x28, 0x32, 0xda, 0x0b, 0x38, 0x28, 0xa5, 0x14, 0x02];
+    data.extend_from_slice(&[0x02, 0x03, 0x02, 0x03]);
+    // Ends at the last but one byte, 247 bytes of strings can't be even.
+    data.extend_from_slice(&[242, 0x03]);
+    data.resize(256, 0x00);
+    let image = EepromImage::parse(&data).unwrap();
+    assert_eq!(image.strings.len(), 3);
+    assert_eq!(image.trailer, vec![0x00]);
+    assert_eq!(image.to_bytes().unwrap(), data);
+    assert_eq!(image.hw_info().unwrap_err().kind(), ErrorKind::StringValueTooLong);
+
+    // One byte too long to fit.
+    data[13] = 244;
+    let image = EepromImage::parse(&data).unwrap();
+    assert_eq!(image.strings.len(), 2);
+    assert_eq!(image.to_bytes().unwrap(), data);
+}
+
+#[test]
+fn non_ascii_strings() {
+    assert_eq!(encode_descriptor("Mü").unwrap(), vec![6, 3, 0x4d, 0, 0xfc, 0]);
+    assert_eq!(encode_descriptor("📡").unwrap(), vec![6, 3, 0x3d, 0xd8, 0xe1, 0xdc]);
+    // An unpaired surrogate.
+    let e = decode_descriptor(&[4, 3, 0x3d, 0xd8]).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::StringDescriptorInvalid);
+    assert_eq!(decode_descriptor(&[4, 1, 0x41, 0]).unwrap_err().kind(),
+               ErrorKind::StringDescriptorInvalid);
+    assert_eq!(decode_descriptor(&[3, 3, 0x41]).unwrap_err().kind(),
+               ErrorKind::StringDescriptorInvalid);
+
+    let dev = SimDevice::new();
+    let mut info = dev.get_hw_info().unwrap();
+    info.manufact = "Müller Funktechnik".to_string();
+    info.product = "Empfänger ™".to_string();
+    // 34 code units in 68 bytes of UTF-8.
+    info.serial = "📡".repeat(17);
+    dev.set_hw_info(&info).unwrap();
+    assert_eq!(dev.get_hw_info().unwrap(), info);
+    dev.replug();
+    assert_eq!(dev.get_usb_strings().unwrap(),
+               (info.manufact.clone(), info.product.clone(), info.serial.clone()));
+
+    info.serial = "📡".repeat(18);
+    assert_eq!(dev.set_hw_info(&info).unwrap_err().kind(), ErrorKind::StringDescriptorTooLong);
+    info.serial = "é".repeat(35);
+    dev.set_hw_info(&info).unwrap();
+}
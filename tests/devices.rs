@@ -0,0 +1,32 @@
+extern crate rtlsdr;
+
+#[test]
+fn usb_ids_by_name() {
+    assert_eq!(rtlsdr::usb_ids("Generic RTL2832U OEM"), Some((0x0bda, 0x2838)));
+    assert_eq!(rtlsdr::usb_ids("Terratec NOXON DAB/DAB+ USB dongle (rev 1)"),
+               Some((0x0ccd, 0x00b3)));
+    assert_eq!(rtlsdr::usb_ids(""), None);
+}
+
+#[test]
+fn lists_every_device() {
+    let devices = rtlsdr::devices().unwrap();
+    assert_eq!(devices.len(), rtlsdr::get_device_count() as usize);
+    for (index, info) in devices.iter().enumerate() {
+        assert_eq!(info.index, index as i32);
+        assert_eq!(info.name, rtlsdr::get_device_name(info.index));
+        assert_eq!(info.usb_ids, rtlsdr::usb_ids(&info.name));
+        assert_eq!(info.tuner, None);
+    }
+}
+
+#[test]
+fn bad_lookup_keys() {
+    let err = rtlsdr::get_index_by_serial("0000\u{0}978".to_string()).unwrap_err();
+    assert_eq!(err.kind(), rtlsdr::ErrorKind::InvalidParam);
+    #[cfg(target_os = "linux")]
+    for path in &["", "1", "1-", "1-1..2", "../1-1", "1-1/serial"] {
+        let err = rtlsdr::open_by_usb_path(path).err().unwrap();
+        assert_eq!(err.kind(), rtlsdr::ErrorKind::InvalidParam, "{}", path);
+    }
+}
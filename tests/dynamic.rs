@@ -0,0 +1,145 @@
+#![cfg(all(feature = "dynamic", unix))]
+
+extern crate libloading;
+extern crate rtlsdr;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rtlsdr::{ErrorKind, Hertz};
+
+// A librtlsdr with one optional function and two dongles, the second with
+// unreadable USB strings, without rtlsdr_cancel_async when BROKEN is
+// defined. stub_bias_tee reports the bias tee state.
+const STUB: &str = r#"
+#include <stdint.h>
+#include <string.h>
+
+static struct { uint32_t freq; int bias_tee; } dev;
+
+uint32_t rtlsdr_get_device_count(void) { return 2; }
+const char *rtlsdr_get_device_name(uint32_t index) {
+    return index ? "Generic RTL2832U OEM" : "Stub RTL2832U";
+}
+int rtlsdr_get_device_usb_strings(uint32_t index, char *m, char *p, char *s) {
+    if (index) return -1;
+    strcpy(m, "Realtek"); strcpy(p, "RTL2838UHIDIR"); strcpy(s, "00000001");
+    return 0;
+}
+int rtlsdr_get_index_by_serial(const char *s) { return strcmp(s, "00000001") ? -3 : 0; }
+int rtlsdr_open(void **d, uint32_t index) { if (index) return -1; *d = &dev; return 0; }
+int rtlsdr_close(void *d) { return 0; }
+int rtlsdr_set_center_freq(void *d, uint32_t freq) { dev.freq = freq; return 0; }
+uint32_t rtlsdr_get_center_freq(void *d) { return dev.freq; }
+int rtlsdr_set_bias_tee(void *d, int on) { dev.bias_tee = on; return 0; }
+int stub_bias_tee(void) { return dev.bias_tee; }
+
+#define STUB(name) int name(void) { return -1; }
+STUB(rtlsdr_set_xtal_freq) STUB(rtlsdr_get_xtal_freq) STUB(rtlsdr_get_usb_strings)
+STUB(rtlsdr_write_eeprom) STUB(rtlsdr_read_eeprom) STUB(rtlsdr_set_freq_correction)
+STUB(rtlsdr_get_freq_correction) STUB(rtlsdr_get_tuner_type) STUB(rtlsdr_get_tuner_gains)
+STUB(rtlsdr_set_tuner_gain) STUB(rtlsdr_set_tuner_bandwidth) STUB(rtlsdr_get_tuner_gain)
+STUB(rtlsdr_set_tuner_if_gain) STUB(rtlsdr_set_tuner_gain_mode) STUB(rtlsdr_set_sample_rate)
+STUB(rtlsdr_get_sample_rate) STUB(rtlsdr_set_testmode) STUB(rtlsdr_set_agc_mode)
+STUB(rtlsdr_set_direct_sampling) STUB(rtlsdr_get_direct_sampling)
+STUB(rtlsdr_set_offset_tuning) STUB(rtlsdr_get_offset_tuning) STUB(rtlsdr_reset_buffer)
+STUB(rtlsdr_read_sync) STUB(rtlsdr_wait_async) STUB(rtlsdr_read_async)
+#ifndef BROKEN
+STUB(rtlsdr_cancel_async)
+#endif
+"#;
+
+// Builds the stub with the system C compiler, `$CC` at build time if set.
+// Without a compiler the test fails rather than passing untested.
+fn build_stub(dir: &Path, name: &str, defines: &[&str]) -> PathBuf {
+    fs::create_dir_all(dir).unwrap();
+    let src = dir.join("stub.c");
+    fs::write(&src, STUB).unwrap();
+    let lib = dir.join(name);
+    let cc = option_env!("CC").unwrap_or("cc");
+    let status = Command::new(cc)
+        .args(["-shared", "-fPIC", "-w", "-o"])
+        .arg(&lib)
+        .arg(&src)
+        .args(defines)
+        .status()
+        .unwrap_or_else(|e| panic!("the stub librtlsdr needs a C compiler, {}: {}", cc, e));
+    assert!(status.success(), "{} failed to build the stub librtlsdr, {}", cc, status);
+    lib
+}
+
+// Loading is once per process, so this is the only test here.
+#[test]
+fn loads_a_stub_library() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("dynamic");
+    let stub = build_stub(&dir, "librtlsdr.so.0.6.0", &[]);
+    let broken = build_stub(&dir, "librtlsdr-broken.so", &["-DBROKEN"]);
+
+    let e = rtlsdr::load_library(dir.join("missing.so")).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotFound);
+    let e = rtlsdr::load_library(&broken).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotSupported);
+    assert_eq!(e.op(), "rtlsdr_cancel_async");
+
+    let info = rtlsdr::load_library(&stub).unwrap();
+    assert!(info.dynamic);
+    assert_eq!(info.path, Some(stub.clone()));
+    assert_eq!(info.version.as_deref(), Some("0.6.0"));
+    assert_eq!(info.functions, vec!["rtlsdr_set_bias_tee"]);
+    assert!(info.has("rtlsdr_set_bias_tee"));
+    assert!(!info.has("rtlsdr_set_dithering"));
+    assert_eq!(rtlsdr::library_info().unwrap(), info);
+    assert_eq!(rtlsdr::load_library(&stub).unwrap_err().kind(), ErrorKind::Busy);
+
+    assert_eq!(rtlsdr::get_device_count(), 2);
+    assert_eq!(rtlsdr::get_device_name(0), "Stub RTL2832U");
+    let devices = rtlsdr::devices().unwrap();
+    assert_eq!(devices.len(), 2);
+    assert_eq!((devices[0].manufacturer.as_str(), devices[0].serial.as_str()),
+               ("Realtek", "00000001"));
+    assert_eq!(devices[0].usb_ids, None);
+    assert_eq!(devices[1].index, 1);
+    assert_eq!(devices[1].name, "Generic RTL2832U OEM");
+    assert_eq!(devices[1].usb_ids, Some((0x0bda, 0x2838)));
+    assert!(devices[1].manufacturer.is_empty() && devices[1].serial.is_empty());
+    assert_eq!(rtlsdr::get_index_by_serial("00000001".to_string()).unwrap(), 0);
+    let dev = rtlsdr::open_by_serial("00000001").unwrap();
+    dev.set_center_freq(Hertz::new(100_000_000)).unwrap();
+    assert_eq!(dev.get_center_freq(), Hertz::new(100_000_000));
+    assert_eq!(dev.set_testmode(true).unwrap_err().kind(), ErrorKind::Io);
+    assert_eq!(dev.read_sync(-1).unwrap_err().kind(), ErrorKind::InvalidParam);
+    let e = dev.set_dithering(false).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotSupported);
+    assert_eq!(e.op(), "rtlsdr_set_dithering");
+
+    // The same handle, so the same stub state.
+    let lib = unsafe { libloading::Library::new(&stub) }.unwrap();
+    let bias_tee = unsafe { lib.get::<unsafe extern "C" fn() -> i32>(b"stub_bias_tee") }.unwrap();
+    let bias_tee = || unsafe { bias_tee() };
+
+    assert!(!dev.get_bias_tee());
+    dev.set_bias_tee(true).unwrap();
+    assert!(dev.get_bias_tee());
+    assert_eq!(bias_tee(), 1);
+    // Without rtlsdr_set_bias_tee_gpio only GPIO 0 can be switched.
+    let e = dev.set_bias_tee_gpio(1, true).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::NotSupported);
+    assert_eq!(e.op(), "rtlsdr_set_bias_tee_gpio");
+    assert!(!dev.get_bias_tee_gpio(1));
+    let e = dev.set_bias_tee_gpio(8, true).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidParam);
+    dev.set_bias_tee_gpio(0, false).unwrap();
+    assert!(!dev.get_bias_tee());
+    assert_eq!(bias_tee(), 0);
+
+    // Closing or dropping the device switches the bias tee off.
+    dev.set_bias_tee(true).unwrap();
+    dev.close().unwrap();
+    assert_eq!(bias_tee(), 0);
+    let dev = rtlsdr::open(0).unwrap();
+    dev.set_bias_tee(true).unwrap();
+    assert_eq!(bias_tee(), 1);
+    drop(dev);
+    assert_eq!(bias_tee(), 0);
+}
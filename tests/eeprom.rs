@@ -0,0 +1,219 @@
+extern crate proptest;
+extern crate rtlsdr;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use rtlsdr::sim::SimDevice;
+use rtlsdr::{EepromImage, ErrorKind, SdrSource};
+
+// Images with a valid header and well formed string descriptors, followed
+// by arbitrary bytes.
+fn valid_image() -> impl Strategy<Value = Vec<u8>> {
+    (any::<[u8; 7]>(), vec(vec(any::<u16>(), 0..36), 3), vec(any::<u8>(), 256))
+        .prop_map(|(fields, strings, fill)| {
+            let mut data = vec![0x28, 0x32];
+            data.extend_from_slice(&fields);
+            for s in strings {
+                data.extend_from_slice(&[(s.len() * 2 + 2) as u8, 0x03]);
+                data.extend(s.iter().flat_map(|u| u.to_le_bytes()));
+            }
+            data.extend_from_slice(&fill);
+            data.truncate(256);
+            data
+        })
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_images_round_trip(data in vec(any::<u8>(), 256)) {
+        let image = EepromImage::parse(&data).unwrap();
+        prop_assert_eq!(image.to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn valid_images_round_trip(data in valid_image()) {
+        let image = EepromImage::parse(&data).unwrap();
+        prop_assert_eq!(image.strings.len(), 3);
+        prop_assert_eq!(image.to_bytes().unwrap(), data.clone());
+        // Strings that aren't valid UTF-16 are refused rather than mangled.
+        match image.hw_info() {
+            Ok(info) => {
+                let mut edited = image.clone();
+                edited.set_hw_info(&info).unwrap();
+                prop_assert_eq!(edited.to_bytes().unwrap(), data);
+            }
+            Err(e) => {
+                prop_assert_eq!(e.kind(), ErrorKind::StringDescriptorInvalid);
+                prop_assert!(image.strings.iter().any(|s| String::from_utf16(s).is_err()));
+            }
+        }
+    }
+
+    #[test]
+    fn edits_keep_the_rest(data in valid_image(), serial in "[0-9A-Za-z]{1,35}") {
+        let mut image = EepromImage::parse(&data).unwrap();
+        let before = image.clone();
+        image.set_serial(&serial).unwrap();
+        image.set_enable_ir(true);
+        let bytes = image.to_bytes();
+        // Only fails when the trailer has no room left for a longer serial.
+        prop_assume!(bytes.is_ok());
+        let reparsed = EepromImage::parse(&bytes.unwrap()).unwrap();
+        prop_assert_eq!(reparsed.serial(), Some(serial));
+        prop_assert!(reparsed.enable_ir());
+        prop_assert_eq!(reparsed.remote_wakeup(), before.remote_wakeup());
+        prop_assert_eq!(reparsed.config & !0x02, before.config & !0x02);
+        prop_assert_eq!(reparsed.reserved, before.reserved);
+        prop_assert_eq!(&reparsed.strings[..2], &before.strings[..2]);
+    }
+}
+
+#[test]
+fn fields() {
+    let dev = SimDevice::new();
+    let image = dev.read_eeprom_image().unwrap();
+    assert!(image.has_valid_header());
+    assert_eq!((image.vendor_id, image.product_id), (0x0bda, 0x2838));
+    assert_eq!(image.manufacturer().as_deref(), Some("Realtek"));
+    assert_eq!(image.product().as_deref(), Some("RTL2838UHIDIR"));
+    assert_eq!(image.serial().as_deref(), Some("00000001"));
+    assert!(image.have_serial());
+    assert_eq!(image.reserved, 0x02);
+
+    let blank = EepromImage::parse(&[0xff; 256]).unwrap();
+    assert!(!blank.has_valid_header());
+    assert!(blank.strings.is_empty());
+    assert_eq!(blank.hw_info().unwrap_err().kind(), ErrorKind::NoValidEEPROMHeader);
+    assert_eq!(EepromImage::parse(&[0xff; 255]).unwrap_err().kind(), ErrorKind::InvalidParam);
+
+    let mut long = image.clone();
+    long.strings[0] = vec![0x41; 127];
+    assert_eq!(long.to_bytes().unwrap_err().kind(), ErrorKind::StringDescriptorTooLong);
+    assert_eq!(long.set_serial(&"9".repeat(36)).unwrap_err().kind(),
+               ErrorKind::StringDescriptorTooLong);
+}
+
+#[test]
+fn set_hw_info_keeps_the_rest() {
+    let dev = SimDevice::new();
+    let mut data = dev.read_eeprom(0, 256).unwrap();
+    data[200..].iter_mut().for_each(|b| *b = 0x5a);
+    dev.write_eeprom(data.clone(), 0).unwrap();
+
+    let mut info = dev.get_hw_info().unwrap();
+    info.serial = "978".to_string();
+    dev.set_hw_info(&info).unwrap();
+    let after = dev.read_eeprom(0, 256).unwrap();
+    assert_eq!(dev.get_hw_info().unwrap(), info);
+    assert_eq!(after[..9], data[..9]);
+    assert_eq!(after[200..], data[200..]);
+}
+
+#[test]
+fn trailer_not_erased() {
+    let mut info = SimDevice::new().get_hw_info().unwrap();
+    info.serial = "RTLSDR-0000000042".to_string();
+
+    // Nothing in an image without a header or strings is worth keeping.
+    let mut image = EepromImage::parse(&[0x00; 256]).unwrap();
+    image.set_hw_info(&info).unwrap();
+    assert_eq!(EepromImage::parse(&image.to_bytes().unwrap()).unwrap().hw_info().unwrap(), info);
+    let dev = SimDevice::new();
+    dev.write_eeprom(vec![0x00; 256], 0).unwrap();
+    dev.set_hw_info(&info).unwrap();
+    assert_eq!(dev.get_hw_info().unwrap(), info);
+
+    // A valid image keeps its trailer unless told to discard it.
+    let dev = SimDevice::new();
+    let mut data = dev.read_eeprom(0, 256).unwrap();
+    let end = 256 - dev.read_eeprom_image().unwrap().trailer.len();
+    data[end..].iter_mut().for_each(|b| *b = 0x00);
+    dev.write_eeprom(data, 0).unwrap();
+    let e = dev.set_hw_info(&info).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::TrailerNotErased);
+    assert_eq!(e.kind().to_string(), "would overwrite non-erased trailer bytes");
+    let mut image = dev.read_eeprom_image().unwrap();
+    image.set_serial(&info.serial).unwrap();
+    assert_eq!(image.to_bytes().unwrap_err().kind(), ErrorKind::TrailerNotErased);
+    image.clear_trailer();
+    dev.write_eeprom_image(&image).unwrap();
+    assert_eq!(dev.get_hw_info().unwrap(), info);
+    assert!(dev.read_eeprom(0, 256).unwrap()[end + 18..].iter().all(|&b| b == 0xff));
+}
+
+#[test]
+fn dump_restore_and_diff() {
+    use rtlsdr::eeprom;
+
+    let path = std::env::temp_dir().join(format!("rtlsdr-eeprom-{}.bin", std::process::id()));
+    let dev = SimDevice::new();
+    let saved = eeprom::dump(&dev, &path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), dev.read_eeprom(0, 256).unwrap());
+
+    let mut edited = saved.clone();
+    edited.set_serial("978").unwrap();
+    edited.set_enable_ir(false);
+    eeprom::write_verified(&dev, &edited, false).unwrap();
+    let diffs = eeprom::diff(&saved, &dev.read_eeprom_image().unwrap());
+    let fields: Vec<&str> = diffs.iter().map(|d| d.field).collect();
+    assert_eq!(fields, vec!["config", "serial"]);
+    assert_eq!(diffs[1].to_string(), "serial: \"00000001\" -> \"978\"");
+
+    assert_eq!(eeprom::restore(&dev, &path, false).unwrap(), saved);
+    assert!(eeprom::diff(&saved, &dev.read_eeprom_image().unwrap()).is_empty());
+
+    let blank = EepromImage::parse(&[0xff; 256]).unwrap();
+    let err = eeprom::write_verified(&dev, &blank, false).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NoValidEEPROMHeader);
+    assert_eq!(dev.read_eeprom_image().unwrap(), saved);
+    eeprom::write_verified(&dev, &blank, true).unwrap();
+    assert_eq!(dev.read_eeprom(0, 256).unwrap(), vec![0xff; 256]);
+
+    let mut trailer = saved.clone();
+    let n = trailer.trailer.len();
+    trailer.trailer[n - 3] = 0x00;
+    let diffs = eeprom::diff(&saved, &trailer);
+    assert_eq!(diffs[0].to_string(), "trailer: ff at 0xfd -> 00 at 0xfd");
+
+    std::fs::write(&path, [0x28, 0x32]).unwrap();
+    assert_eq!(eeprom::load(&path).unwrap_err().kind(), ErrorKind::InvalidParam);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn presets() {
+    use rtlsdr::eeprom;
+
+    let oem = eeprom::preset("Realtek_OEM").unwrap();
+    let dev = SimDevice::new();
+    // The simulated dongle ships with the Realtek OEM configuration.
+    assert_eq!(oem.image("00000001").unwrap(), dev.read_eeprom_image().unwrap());
+    let realtek = eeprom::preset("realtek").unwrap().image("0").unwrap();
+    assert_eq!((realtek.vendor_id, realtek.product_id), (0x0bda, 0x2832));
+    assert_eq!(realtek.product().as_deref(), Some("RTL2832U DVB-T"));
+    assert!(realtek.remote_wakeup() && !realtek.enable_ir());
+    assert!(eeprom::preset("nope").is_none());
+    for p in eeprom::PRESETS {
+        let image = p.image("0").unwrap();
+        assert_eq!(image.to_bytes().unwrap().len(), 256);
+        assert_eq!(image.hw_info().unwrap().manufact, p.manufacturer);
+    }
+
+    let mut info = dev.get_hw_info().unwrap();
+    info.serial = "00000978".to_string();
+    dev.set_hw_info(&info).unwrap();
+    let before = dev.read_eeprom(0, 256).unwrap();
+    let noxon = eeprom::preset("noxon").unwrap();
+    let rendered = eeprom::render_preset(&dev, noxon).unwrap();
+    assert_eq!(dev.read_eeprom(0, 256).unwrap(), before);
+    let dump = rendered.hexdump().unwrap();
+    assert_eq!(dump.lines().count(), 16);
+    assert!(dump.starts_with("0x00: 28 32 cd 0c b3 00 a5 15 02 0c 03 4e 00 4f 00 58\n"));
+
+    assert_eq!(eeprom::apply_preset(&dev, noxon).unwrap(), rendered);
+    let info = dev.get_hw_info().unwrap();
+    assert_eq!((info.vendor_id, info.product_id), (0x0ccd, 0x00b3));
+    assert_eq!((info.product.as_str(), info.serial.as_str()), ("DAB Stick", "00000978"));
+    assert!(info.remote_wakeup && !info.enable_ir);
+}
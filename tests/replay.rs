@@ -0,0 +1,137 @@
+extern crate rtlsdr;
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use rtlsdr::replay::{FileSource, Pacing, ReplayOptions};
+use rtlsdr::{AsyncControl, ErrorKind, Hertz, SampleRate, SdrSource, MIN_BUF_LENGTH};
+
+// A capture of `len` bytes counting up from zero.
+fn capture(name: &str, len: usize) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rtlsdr-{}-{}.cu8", name, std::process::id()));
+    fs::write(&path, (0..len).map(|i| i as u8).collect::<Vec<_>>()).unwrap();
+    path
+}
+
+#[test]
+fn async_buffers_cover_file() {
+    let path = capture("async", 3 * 1024 + 100);
+    let src = FileSource::open(&path, ReplayOptions::default()).unwrap();
+    let mut lens = Vec::new();
+    let mut next = 0u8;
+    src.read_async(1, 1024, &mut |buf| {
+        assert!(buf.iter().all(|&b| {
+            let ok = b == next;
+            next = next.wrapping_add(1);
+            ok
+        }));
+        lens.push(buf.len());
+    })
+    .unwrap();
+    assert_eq!(lens, vec![1024, 1024, 1024, 100]);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn odd_buffer_length_uses_default() {
+    let path = capture("default", 300_000);
+    let src = FileSource::open(&path, ReplayOptions::default()).unwrap();
+    let mut first = None;
+    src.read_async(1, MIN_BUF_LENGTH + 1, &mut |buf| {
+        first.get_or_insert(buf.len());
+    })
+    .unwrap();
+    assert_eq!(first, Some(rtlsdr::DEFAULT_BUF_LENGTH as usize));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn looping_and_seeking() {
+    let path = capture("loop", 1000);
+    let opts = ReplayOptions {
+        looping: true,
+        ..ReplayOptions::default()
+    };
+    let src = FileSource::open(&path, opts).unwrap();
+    assert_eq!(src.len(), 500);
+
+    src.seek_sample(450).unwrap();
+    let buf = src.read_sync(200).unwrap();
+    assert_eq!(buf.len(), 200);
+    assert_eq!(buf[0], 900u32 as u8);
+    assert_eq!(buf[100], 0);
+    assert_eq!(src.position(), 50);
+
+    src.seek_time(Duration::from_secs_f64(100.0 / 2_048_000.0)).unwrap();
+    assert_eq!(src.position(), 100);
+    assert_eq!(src.seek_sample(501).unwrap_err().kind(), ErrorKind::InvalidParam);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn duration() {
+    let path = capture("duration", 2 * 1_024_000);
+    let src = FileSource::open(&path, ReplayOptions::default()).unwrap();
+    assert_eq!(src.duration(), Duration::from_millis(500));
+    let opts = ReplayOptions {
+        sample_rate: SampleRate::new(0),
+        ..ReplayOptions::default()
+    };
+    let src = FileSource::open(&path, opts).unwrap();
+    assert_eq!(src.duration(), Duration::ZERO);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn read_async_after_a_panic() {
+    let path = capture("panic", 2048);
+    let src = FileSource::open(&path, ReplayOptions::default()).unwrap();
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        src.read_async_until(1, 512, &mut |_| panic!("callback"))
+    }));
+    assert!(res.is_err());
+    src.read_async_until(1, 512, &mut |_| AsyncControl::Stop).unwrap();
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn end_of_file() {
+    let path = capture("eof", 600);
+    let src = FileSource::open(&path, ReplayOptions::default()).unwrap();
+    assert_eq!(src.read_sync(512).unwrap().len(), 512);
+    assert_eq!(src.read_sync(512).unwrap().len(), 88);
+    assert!(src.read_sync(512).unwrap().is_empty());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn real_time_pacing() {
+    let path = capture("pace", 2 * 25_000);
+    let opts = ReplayOptions {
+        sample_rate: SampleRate::new(250_000),
+        pacing: Pacing::RealTime,
+        ..ReplayOptions::default()
+    };
+    let src = FileSource::open(&path, opts).unwrap();
+    let start = Instant::now();
+    while !src.read_sync(5_000).unwrap().is_empty() {}
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn recorded_tuning_only() {
+    let path = capture("tune", 512);
+    let opts = ReplayOptions {
+        center_freq: Hertz::new(978_000_000),
+        ..ReplayOptions::default()
+    };
+    let src = FileSource::open(&path, opts).unwrap();
+    src.set_center_freq(Hertz::new(978_000_000)).unwrap();
+    src.set_sample_rate(SampleRate::new(2_048_000)).unwrap();
+    let err = src.set_center_freq(Hertz::new(1_090_000_000)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotSupported);
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,206 @@
+extern crate rtlsdr;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use rtlsdr::tcp::TcpSource;
+use rtlsdr::{AsyncControl, ErrorKind, Hertz, Ppm, SampleRate, SamplingMode, SdrSource,
+             TenthDb, TunerType};
+
+// A fake rtl_tcp server with an R820T, it streams a counter and reports the
+// commands it receives.
+fn fake_server() -> (String, Receiver<(u8, u32)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.write_all(b"RTL0\x00\x00\x00\x05\x00\x00\x00\x1d").unwrap();
+        let mut cmds = conn.try_clone().unwrap();
+        thread::spawn(move || {
+            let mut packet = [0u8; 5];
+            while cmds.read_exact(&mut packet).is_ok() {
+                let param = u32::from_be_bytes([packet[1], packet[2], packet[3], packet[4]]);
+                if tx.send((packet[0], param)).is_err() {
+                    break;
+                }
+            }
+        });
+        let data: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+        while conn.write_all(&data).is_ok() {}
+    });
+    (addr, rx)
+}
+
+fn next(rx: &Receiver<(u8, u32)>) -> (u8, u32) {
+    rx.recv_timeout(Duration::from_secs(5)).unwrap()
+}
+
+#[test]
+fn header_and_commands() {
+    let (addr, rx) = fake_server();
+    let dev = TcpSource::connect(addr).unwrap();
+    assert_eq!(dev.get_tuner_type(), TunerType::R820T);
+    assert_eq!(dev.gain_count(), 29);
+    assert_eq!(dev.get_tuner_gains().unwrap().len(), 29);
+
+    dev.set_center_freq(Hertz::new(978_000_000)).unwrap();
+    assert_eq!(next(&rx), (0x01, 978_000_000));
+    dev.set_sample_rate(SampleRate::new(2_048_000)).unwrap();
+    assert_eq!(next(&rx), (0x02, 2_048_000));
+    assert!(dev.set_sample_rate(SampleRate::new(500_000)).is_err());
+    dev.set_tuner_gain_mode(true).unwrap();
+    assert_eq!(next(&rx), (0x03, 1));
+    dev.set_tuner_gain(TenthDb::new(496)).unwrap();
+    assert_eq!(next(&rx), (0x04, 496));
+    dev.set_freq_correction(Ppm::new(-3)).unwrap();
+    assert_eq!(next(&rx), (0x05, -3i32 as u32));
+    dev.set_tuner_if_gain(2, TenthDb::new(30)).unwrap();
+    assert_eq!(next(&rx), (0x06, 2 << 16 | 30));
+    dev.set_testmode(true).unwrap();
+    assert_eq!(next(&rx), (0x07, 1));
+    dev.set_agc_mode(true).unwrap();
+    assert_eq!(next(&rx), (0x08, 1));
+    dev.set_direct_sampling(SamplingMode::QADC).unwrap();
+    assert_eq!(next(&rx), (0x09, 2));
+    dev.set_offset_tuning(true).unwrap();
+    assert_eq!(next(&rx), (0x0a, 1));
+    dev.set_xtal_freq(Hertz::new(28_800_100), Hertz::new(28_800_200)).unwrap();
+    assert_eq!(next(&rx), (0x0b, 28_800_100));
+    assert_eq!(next(&rx), (0x0c, 28_800_200));
+    dev.set_tuner_gain_by_index(3).unwrap();
+    assert_eq!(next(&rx), (0x0d, 3));
+    dev.set_bias_tee(true).unwrap();
+    assert_eq!(next(&rx), (0x0e, 1));
+
+    assert_eq!(dev.get_center_freq(), Hertz::new(978_000_000));
+    assert_eq!(dev.get_direct_sampling(), SamplingMode::QADC);
+    assert!(dev.get_bias_tee());
+    assert!(SdrSource::get_bias_tee_gpio(&dev, 0));
+    assert_eq!(dev.set_bias_tee_gpio(1, true).unwrap_err().kind(), ErrorKind::NotSupported);
+    assert!(!dev.get_bias_tee_gpio(1));
+}
+
+#[test]
+fn samples() {
+    let (addr, _rx) = fake_server();
+    let dev = TcpSource::connect(addr).unwrap();
+    let buf = dev.read_sync(1000).unwrap();
+    assert!(buf.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+    let mut count = 0;
+    dev.read_async_until(1, 1024, &mut |buf| {
+        assert_eq!(buf.len(), 1024);
+        assert_eq!(dev.read_sync(2).unwrap_err().kind(), ErrorKind::Busy);
+        count += 1;
+        if count == 3 {
+            AsyncControl::Stop
+        } else {
+            AsyncControl::Continue
+        }
+    })
+    .unwrap();
+    assert_eq!(count, 3);
+
+    // A panicking callback doesn't leave the source busy.
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        dev.read_async_until(1, 1024, &mut |_| panic!("callback"))
+    }));
+    assert!(res.is_err());
+    dev.read_async_until(1, 1024, &mut |_| AsyncControl::Stop).unwrap();
+}
+
+// A fake rtl_tcp server that streams a counter, pausing after `first`
+// bytes until told to go on.
+fn pausing_server(first: usize) -> (String, Sender<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.write_all(b"RTL0\x00\x00\x00\x05\x00\x00\x00\x1d").unwrap();
+        let data: Vec<u8> = (0..first).map(|i| i as u8).collect();
+        conn.write_all(&data).unwrap();
+        rx.recv().unwrap();
+        let data: Vec<u8> = (first..first + 4096).map(|i| i as u8).collect();
+        while conn.write_all(&data).is_ok() {}
+    });
+    (addr, tx)
+}
+
+#[test]
+fn cancel_mid_buffer() {
+    let (addr, resume) = pausing_server(1024 + 101);
+    let dev = TcpSource::connect(addr).unwrap();
+    let canceller = dev.canceller();
+    let mut count = 0;
+    dev.read_async_until(1, 1024, &mut |_| {
+        count += 1;
+        if count == 1 {
+            // Cancel while the second buffer is half read.
+            let canceller = canceller.clone();
+            let resume = resume.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                canceller.cancel_async().unwrap();
+                thread::sleep(Duration::from_millis(100));
+                resume.send(()).unwrap();
+            });
+        }
+        AsyncControl::Continue
+    })
+    .unwrap();
+    // The second buffer was finished, the next read starts on an I sample.
+    assert_eq!(count, 2);
+    assert_eq!(dev.read_sync(2).unwrap(), vec![0, 1]);
+}
+
+#[test]
+fn bad_header() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.write_all(b"HTTP/1.1 200").unwrap();
+    });
+    assert!(TcpSource::connect(addr).is_err());
+}
+
+#[test]
+fn server_over_sim() {
+    use rtlsdr::sim::SimDevice;
+    use rtlsdr::tcp::{Server, ServerOptions};
+
+    let sim = SimDevice::new();
+    sim.set_testmode(true).unwrap();
+    let server = Server::bind("127.0.0.1:0", sim, ServerOptions::default()).unwrap();
+    let addr = server.local_addr().unwrap();
+    let serving = thread::spawn(move || {
+        server.serve_one().unwrap();
+        server.into_device()
+    });
+
+    let client = TcpSource::connect(addr).unwrap();
+    assert_eq!(client.get_tuner_type(), TunerType::R820T);
+    assert_eq!(client.gain_count(), 29);
+    let buf = client.read_sync(1024).unwrap();
+    assert!(buf.windows(2).all(|w| w[1] == w[0].wrapping_add(1)));
+
+    client.set_center_freq(Hertz::new(978_000_000)).unwrap();
+    client.set_tuner_gain_mode(true).unwrap();
+    client.set_tuner_gain_by_index(28).unwrap();
+    client.set_bias_tee(true).unwrap();
+    // Commands are applied between buffers, keep them flowing.
+    client.read_sync(256 * 1024).unwrap();
+    client.close().unwrap();
+
+    let sim = serving.join().unwrap();
+    assert_eq!(sim.get_center_freq(), Hertz::new(978_000_000));
+    assert_eq!(sim.get_tuner_gain(), TenthDb::new(496));
+    assert!(sim.get_bias_tee());
+    assert!(!sim.get_bias_tee_gpio(1));
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "serde")]
+
+extern crate rtlsdr;
+
+use rtlsdr::sim::SimDevice;
+use rtlsdr::{DeviceConfig, Error, Gain, Hertz, HwInfo, Ppm, SampleRate, SamplingMode, SdrSource,
+             TenthDb, TunerType};
+
+#[test]
+fn profile_round_trip() {
+    let config = DeviceConfig::new()
+        .xtal_freq(Hertz::new(28_800_000), Hertz::new(28_800_000))
+        .freq_correction(Ppm::new(-3))
+        .direct_sampling(SamplingMode::None)
+        .sample_rate(SampleRate::new(2_400_000))
+        .center_freq(Hertz::new(1_090_000_000))
+        .gain(Gain::Manual(TenthDb::new(297)))
+        .if_gain(1, TenthDb::new(30))
+        .agc(false);
+    let text = config.to_toml().unwrap();
+    assert_eq!(DeviceConfig::from_toml(&text).unwrap(), config);
+    assert!(DeviceConfig::from_toml("center_freq = \"fast\"").is_err());
+}
+
+#[test]
+fn profile_file() {
+    let path = std::env::temp_dir().join(format!("rtlsdr-profile-{}.toml", std::process::id()));
+    std::fs::write(&path,
+                   "freq_correction = 5\nsample_rate = 1024000\ncenter_freq = 433920000\n\
+                    gain = \"Max\"\n")
+        .unwrap();
+    let config = DeviceConfig::load(&path).unwrap();
+    assert_eq!(config.gain, Some(Gain::Max));
+    assert_eq!(config.bandwidth, None);
+
+    let dev = SimDevice::new();
+    let readback = dev.apply(&config).unwrap();
+    assert_eq!(readback.center_freq, Some(Hertz::new(433_920_000)));
+    assert_eq!(readback.gain, Some(Gain::Manual(TenthDb::new(496))));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn info_types() {
+    let info = SimDevice::new().get_hw_info().unwrap();
+    let text = toml::to_string(&info).unwrap();
+    assert!(text.contains("serial = \"00000001\""));
+    assert_eq!(toml::from_str::<HwInfo>(&text).unwrap(), info);
+
+    let err = SimDevice::new().set_sample_rate(SampleRate::new(1)).unwrap_err();
+    let text = toml::to_string(&err).unwrap();
+    assert_eq!(toml::from_str::<Error>(&text).unwrap(), err);
+
+    let caps = TunerType::E4000.capabilities();
+    let text = toml::to_string(&caps).unwrap();
+    assert!(text.contains("tuner = \"E4000\""));
+}
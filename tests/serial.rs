@@ -0,0 +1,83 @@
+extern crate rtlsdr;
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use rtlsdr::serial::{self, Change, Scheme};
+use rtlsdr::sim::SimDevice;
+use rtlsdr::{ErrorKind, SdrSource};
+
+fn serials(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn plan_schemes() {
+    let current = serials(&["00000001", "00000001", "00000002", "00000001"]);
+    assert_eq!(serial::duplicates(&current), serials(&["00000001"]));
+
+    let changes = serial::plan(&current, &Scheme::Sequential { start: 1 }).unwrap();
+    let new: Vec<(usize, &str)> = changes.iter().map(|c| (c.index, c.new.as_str())).collect();
+    assert_eq!(new, vec![(1, "00000003"), (3, "00000004")]);
+
+    let changes = serial::plan(&current, &Scheme::Random).unwrap();
+    assert_eq!(changes.len(), 2);
+    let mut after = current.clone();
+    for c in &changes {
+        assert_eq!(c.new.len(), 8);
+        after[c.index] = c.new.clone();
+    }
+    assert!(serial::duplicates(&after).is_empty());
+
+    let mut map = BTreeMap::new();
+    map.insert(1, "adsb".to_string());
+    map.insert(3, "ais".to_string());
+    assert_eq!(serial::plan(&current, &Scheme::Mapped(map.clone())).unwrap().len(), 2);
+    map.insert(3, "00000002".to_string());
+    assert!(serial::plan(&current, &Scheme::Mapped(map.clone())).is_err());
+    map.insert(9, "uat".to_string());
+    assert!(serial::plan(&current, &Scheme::Mapped(map)).is_err());
+
+    let unique = serials(&["a", "b"]);
+    assert!(serial::plan(&unique, &Scheme::Random).unwrap().is_empty());
+    for bad in &["", "has space", "ünï", "0123456789012345678901234567890123456"] {
+        assert_eq!(serial::check_serial(bad).unwrap_err().kind(), ErrorKind::InvalidParam);
+    }
+}
+
+#[test]
+fn assign_backs_up_and_verifies() {
+    let dir = std::env::temp_dir().join(format!("rtlsdr-serial-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let dongles = [SimDevice::new(), SimDevice::new()];
+    let original = dongles[1].read_eeprom(0, 256).unwrap();
+    let current: Vec<String> =
+        dongles.iter().map(|d| d.get_usb_strings().unwrap().2).collect();
+    let changes = serial::plan(&current, &Scheme::Sequential { start: 1 }).unwrap();
+    assert_eq!(changes,
+               vec![Change {
+                        index: 1,
+                        old: "00000001".to_string(),
+                        new: "00000002".to_string(),
+                    }]);
+
+    // Only the dongle being changed is given.
+    let reports = serial::assign(&[(1, &dongles[1])], &changes, &dir, false).unwrap();
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].needs_replug);
+    assert_eq!(fs::read(&reports[0].backup).unwrap(), original);
+    assert_eq!(dongles[1].get_hw_info().unwrap().serial, "00000002");
+    assert_eq!(dongles[0].get_hw_info().unwrap().serial, "00000001");
+    dongles[1].replug();
+    assert_eq!(dongles[1].get_usb_strings().unwrap().2, "00000002");
+
+    let bad = vec![Change {
+                       index: 5,
+                       old: String::new(),
+                       new: "00000009".to_string(),
+                   }];
+    let both = [(0, &dongles[0]), (1, &dongles[1])];
+    let err = serial::assign(&both, &bad, &dir, false).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidParam);
+    fs::remove_dir_all(dir).unwrap();
+}
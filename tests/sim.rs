@@ -0,0 +1,314 @@
+extern crate rtlsdr;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rtlsdr::sim::{Signal, SimDevice};
+use rtlsdr::{AsyncControl, DeviceConfig, ErrorKind, Gain, Hertz, Overflow, Ppm, SampleRate,
+             SdrSource, Stream, StreamOptions, TenthDb, TunerType};
+
+// Power of `iq` at `offset_hz` from the center, normalised to full scale.
+fn power_at(iq: &[u8], rate: f64, offset_hz: f64) -> f64 {
+    let (mut re, mut im) = (0.0, 0.0);
+    let n = iq.len() / 2;
+    for (k, s) in iq.chunks(2).enumerate() {
+        let i = (s[0] as f64 - 127.5) / 127.5;
+        let q = (s[1] as f64 - 127.5) / 127.5;
+        let w = -2.0 * std::f64::consts::PI * offset_hz * k as f64 / rate;
+        re += i * w.cos() - q * w.sin();
+        im += i * w.sin() + q * w.cos();
+    }
+    (re * re + im * im).sqrt() / n as f64
+}
+
+#[test]
+fn tone_follows_center_freq() {
+    let dev = SimDevice::new();
+    dev.set_sample_rate(SampleRate::new(2_048_000)).unwrap();
+    dev.set_center_freq(Hertz::new(100_000_000)).unwrap();
+    dev.add_signal(Signal::Tone {
+        freq_hz: 100_250_000.0,
+        amplitude: 0.5,
+    });
+    let buf = dev.read_sync(16_384).unwrap();
+    assert!(power_at(&buf, 2_048_000.0, 250_000.0) > 0.4);
+    assert!(power_at(&buf, 2_048_000.0, -250_000.0) < 0.05);
+
+    dev.set_center_freq(Hertz::new(100_500_000)).unwrap();
+    let buf = dev.read_sync(16_384).unwrap();
+    assert!(power_at(&buf, 2_048_000.0, -250_000.0) > 0.4);
+}
+
+#[test]
+fn output_is_deterministic() {
+    let read = || {
+        let dev = SimDevice::new();
+        dev.set_seed(7);
+        dev.set_sample_rate(SampleRate::new(1_024_000)).unwrap();
+        dev.set_center_freq(Hertz::new(433_920_000)).unwrap();
+        dev.add_signal(Signal::Noise { amplitude: 0.1 });
+        dev.add_signal(Signal::Fm {
+            freq_hz: 433_950_000.0,
+            amplitude: 0.3,
+            mod_freq_hz: 1_000.0,
+            deviation_hz: 5_000.0,
+        });
+        dev.read_sync(4096).unwrap()
+    };
+    assert_eq!(read(), read());
+}
+
+#[test]
+fn testmode_counts() {
+    let dev = SimDevice::new();
+    dev.set_testmode(true).unwrap();
+    let buf = dev.read_sync(512).unwrap();
+    assert!(buf.iter().enumerate().all(|(i, &b)| b == i as u8));
+}
+
+#[test]
+fn sample_rate_ranges() {
+    let dev = SimDevice::new();
+    for &rate in &[225_000, 300_001, 900_000, 3_200_001] {
+        let err = dev.set_sample_rate(SampleRate::new(rate)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidParam);
+    }
+    dev.set_sample_rate(SampleRate::new(2_083_334)).unwrap();
+    assert_eq!(dev.get_sample_rate(), SampleRate::new(2_083_334));
+}
+
+#[test]
+fn exact_sample_rates() {
+    let xtal = Hertz::new(28_800_000);
+    assert!(rtlsdr::check_sample_rate(SampleRate::new(300_000)).is_ok());
+    assert!(rtlsdr::check_sample_rate(SampleRate::new(300_001)).is_err());
+    assert_eq!(rtlsdr::exact_sample_rate(xtal, SampleRate::new(2_048_000)), 2_048_000.0);
+    let nearest = rtlsdr::nearest_exact_sample_rate(xtal, SampleRate::new(2_083_334)).unwrap();
+    assert_eq!(nearest, SampleRate::new(2_097_152));
+    assert_eq!(rtlsdr::exact_sample_rate(xtal, nearest), nearest.as_f64());
+    let nearest = rtlsdr::nearest_exact_sample_rate(xtal, SampleRate::new(600_000)).unwrap();
+    assert_eq!(nearest, SampleRate::new(300_000));
+
+    let dev = SimDevice::new();
+    dev.set_freq_correction(Ppm::new(-35)).unwrap();
+    dev.set_sample_rate(SampleRate::new(2_083_334)).unwrap();
+    let exact = dev.get_exact_sample_rate().unwrap();
+    assert!(exact > 2_083_334.0 && exact < 2_083_335.0);
+    let rate = dev.set_exact_sample_rate(SampleRate::new(2_083_334)).unwrap();
+    assert_eq!(rate, SampleRate::new(2_097_152));
+    assert_eq!(dev.get_exact_sample_rate().unwrap(), rate.as_f64());
+}
+
+#[test]
+fn gain_snaps_to_table() {
+    let dev = SimDevice::with_tuner(TunerType::E4000);
+    dev.set_tuner_gain_mode(true).unwrap();
+    dev.set_tuner_gain(TenthDb::new(100)).unwrap();
+    assert_eq!(dev.get_tuner_gain(), TenthDb::new(90));
+    assert!(dev.set_center_freq(Hertz::new(1_200_000_000)).is_err());
+}
+
+#[test]
+fn gain_presets() {
+    let dev = SimDevice::new();
+    assert_eq!(dev.get_gain(), Gain::Auto);
+    assert_eq!(dev.set_gain_db(20.0).unwrap(), TenthDb::new(197));
+    assert!(dev.get_tuner_gain_mode());
+    assert_eq!(dev.set_gain(Gain::Max).unwrap(), Gain::Manual(TenthDb::new(496)));
+    assert_eq!(dev.set_gain(Gain::Min).unwrap(), Gain::Manual(TenthDb::new(0)));
+    assert_eq!(dev.get_gain(), Gain::Manual(TenthDb::new(0)));
+    assert_eq!(dev.set_gain(Gain::Auto).unwrap(), Gain::Auto);
+    assert_eq!(dev.get_gain(), Gain::Auto);
+    assert!(dev.set_gain_db(f64::NAN).is_err());
+
+    let dev = SimDevice::with_tuner(TunerType::Unknown);
+    assert!(dev.set_gain(Gain::Max).is_err());
+}
+
+#[test]
+fn apply_config() {
+    let dev = SimDevice::new();
+    let config = DeviceConfig::new()
+        .freq_correction(Ppm::new(5))
+        .offset_tuning(false)
+        .sample_rate(SampleRate::new(2_400_000))
+        .center_freq(Hertz::new(1_090_000_000))
+        .gain(Gain::Manual(TenthDb::new(300)));
+    let readback = dev.apply(&config).unwrap();
+    assert_eq!(readback.freq_correction, Some(Ppm::new(5)));
+    assert_eq!(readback.sample_rate, Some(SampleRate::new(2_400_000)));
+    assert_eq!(readback.center_freq, Some(Hertz::new(1_090_000_000)));
+    assert_eq!(readback.gain, Some(Gain::Manual(TenthDb::new(297))));
+
+    // Out of range for the R820T, the earlier settings are rolled back.
+    let config = DeviceConfig::new()
+        .sample_rate(SampleRate::new(1_024_000))
+        .center_freq(Hertz::new(2_000_000_000));
+    let err = dev.apply(&config).unwrap_err();
+    assert_eq!(err.setting, "center_freq");
+    assert!(err.rolled_back);
+    assert_eq!(err.applied, vec!["sample_rate"]);
+    assert!(err.not_restored.is_empty());
+    assert_eq!(DeviceConfig::read_from(&dev), readback);
+
+    // Test mode can't be read back, the settings after it are still restored.
+    let config = DeviceConfig::new()
+        .testmode(false)
+        .sample_rate(SampleRate::new(1_024_000))
+        .center_freq(Hertz::new(2_000_000_000));
+    let err = dev.apply(&config).unwrap_err();
+    assert!(!err.rolled_back);
+    assert_eq!(err.applied, vec!["testmode", "sample_rate"]);
+    assert_eq!(err.not_restored, vec!["testmode"]);
+    assert_eq!(err.to_string(), format!("setting center_freq failed, {}, testmode not restored",
+                                        err.error));
+    assert_eq!(DeviceConfig::read_from(&dev), readback);
+
+    let config = DeviceConfig::new().agc(true).gain(Gain::Manual(TenthDb::new(1000)));
+    let err = SimDevice::with_tuner(TunerType::Unknown).apply(&config).unwrap_err();
+    assert_eq!(err.setting, "gain");
+}
+
+#[test]
+fn capabilities() {
+    let caps = SimDevice::with_tuner(TunerType::E4000).capabilities();
+    assert_eq!(caps.tuner, TunerType::E4000);
+    assert_eq!(caps.freq_ranges.len(), 2);
+    assert!(caps.tunes_to(Hertz::new(1_000_000_000)));
+    assert!(!caps.tunes_to(Hertz::new(1_200_000_000)));
+    assert_eq!(caps.gains.len(), 14);
+    assert_eq!(caps.if_gain_stages, 6);
+    assert!(caps.offset_tuning);
+
+    let caps = SimDevice::new().capabilities();
+    assert!(!caps.offset_tuning);
+    assert!(caps.bandwidth);
+    assert!(caps.supports_sample_rate(SampleRate::new(2_048_000)));
+    assert!(!caps.supports_sample_rate(SampleRate::new(500_000)));
+
+    assert!(TunerType::FC2580.capabilities().gains.is_empty());
+    assert_eq!("r828d".parse::<TunerType>().unwrap(), TunerType::R828D);
+    assert_eq!(TunerType::from_raw(42), TunerType::Unknown);
+}
+
+#[test]
+fn hw_info_round_trip() {
+    let dev = SimDevice::new();
+    let mut info = dev.get_hw_info().unwrap();
+    assert_eq!(info.serial, "00000001");
+    info.serial = "00000042".to_string();
+    dev.set_hw_info(&info).unwrap();
+    assert_eq!(dev.get_hw_info().unwrap().serial, "00000042");
+    assert_eq!(dev.get_usb_strings().unwrap().2, "00000001");
+    dev.replug();
+    assert_eq!(dev.get_usb_strings().unwrap().2, "00000042");
+}
+
+#[test]
+fn read_async_after_a_panic() {
+    let dev = SimDevice::new();
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        dev.read_async_until(1, 512, &mut |_| panic!("callback"))
+    }));
+    assert!(res.is_err());
+    let mut nested = None;
+    dev.read_async_until(1, 512, &mut |_| {
+        nested = Some(dev.read_async_until(1, 512, &mut |_| AsyncControl::Stop));
+        AsyncControl::Stop
+    })
+    .unwrap();
+    assert_eq!(nested.unwrap().unwrap_err().kind(), ErrorKind::Busy);
+}
+
+#[test]
+fn stream_from_sim() {
+    let dev = SimDevice::new();
+    dev.set_testmode(true).unwrap();
+    let opts = StreamOptions {
+        buf_len: 1024,
+        ..StreamOptions::default()
+    };
+    let mut stream = Stream::new(dev, opts);
+    let bufs: Vec<_> = stream.by_ref().take(3).map(|b| b.unwrap()).collect();
+    assert_eq!(bufs.iter().map(|b| b.seq()).collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert!(bufs.iter().all(|b| b.len() == 1024 && b[1] == 1));
+    stream.stop();
+}
+
+// A stream over the sim with a four buffer queue, left unread until
+// `dropped` buffers have overflowed it.
+fn overflowed_stream(overflow: Overflow, dropped: u64) -> Stream<SimDevice> {
+    let opts = StreamOptions {
+        buf_len: 512,
+        capacity: 4,
+        overflow,
+        ..StreamOptions::default()
+    };
+    let stream = Stream::new(SimDevice::new(), opts);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while stream.dropped() < dropped {
+        assert!(Instant::now() < deadline, "only {} dropped", stream.dropped());
+        thread::sleep(Duration::from_millis(1));
+    }
+    stream
+}
+
+fn seqs(stream: &Stream<SimDevice>, n: usize) -> Vec<u64> {
+    (0..n).map(|_| stream.recv().unwrap().unwrap().seq()).collect()
+}
+
+#[test]
+fn overflow_drop_oldest() {
+    let stream = overflowed_stream(Overflow::DropOldest, 10);
+    // The queue holds the latest buffers, everything before them was dropped.
+    let queued = seqs(&stream, 4);
+    assert!(queued[0] >= 10);
+    assert!(queued.windows(2).all(|w| w[0] < w[1]));
+    assert!(stream.dropped() >= queued[0]);
+    stream.stop();
+}
+
+#[test]
+fn overflow_drop_newest() {
+    let stream = overflowed_stream(Overflow::DropNewest, 10);
+    // The queue keeps the first buffers, the ones after them were dropped.
+    assert_eq!(seqs(&stream, 4), vec![0, 1, 2, 3]);
+    let next = seqs(&stream, 1)[0];
+    assert!(next >= 14);
+    assert!(stream.dropped() >= next - 4);
+    stream.stop();
+}
+
+#[test]
+fn overflow_block() {
+    let opts = StreamOptions {
+        buf_len: 512,
+        capacity: 4,
+        ..StreamOptions::default()
+    };
+    let stream = Stream::new(SimDevice::new(), opts);
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(seqs(&stream, 100), (0..100).collect::<Vec<_>>());
+    assert_eq!(stream.dropped(), 0);
+    stream.stop();
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_device_over_sim() {
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use rtlsdr::AsyncDevice;
+
+    let dev = AsyncDevice::new(SimDevice::new());
+    block_on(async {
+        dev.set_sample_rate(SampleRate::new(1_024_000)).await.unwrap();
+        let mut samples = dev.stream(StreamOptions::default());
+        samples.next().await.unwrap().unwrap();
+        dev.set_center_freq(Hertz::new(978_000_000)).await.unwrap();
+        samples.next().await.unwrap().unwrap();
+        assert_eq!(dev.get_center_freq().await.unwrap(), Hertz::new(978_000_000));
+    });
+    drop(dev.into_device());
+}
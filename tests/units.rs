@@ -0,0 +1,48 @@
+extern crate rtlsdr;
+
+use std::convert::TryFrom;
+
+use rtlsdr::{ErrorKind, Hertz, Ppm, SampleRate, TenthDb};
+
+#[test]
+fn parse() {
+    assert_eq!("978M".parse::<Hertz>().unwrap(), Hertz::new(978_000_000));
+    assert_eq!("1.09 GHz".parse::<Hertz>().unwrap(), Hertz::new(1_090_000_000));
+    assert_eq!("2200MHz".parse::<Hertz>().unwrap(), Hertz::new(2_200_000_000));
+    assert_eq!("125k".parse::<Hertz>().unwrap(), Hertz::new(125_000));
+    assert_eq!("2.048Msps".parse::<SampleRate>().unwrap(), SampleRate::new(2_048_000));
+    assert_eq!("250000".parse::<SampleRate>().unwrap(), SampleRate::new(250_000));
+    assert_eq!("49.6dB".parse::<TenthDb>().unwrap(), TenthDb::new(496));
+    assert_eq!("-1".parse::<TenthDb>().unwrap(), TenthDb::new(-10));
+    assert_eq!("-3ppm".parse::<Ppm>().unwrap(), Ppm::new(-3));
+
+    for bad in &["", "M", "978X", "-1M", "5G"] {
+        let err = bad.parse::<Hertz>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidParam, "{}", bad);
+    }
+}
+
+#[test]
+fn display_round_trips() {
+    for &hz in &[0, 999, 28_800_000, 978_000_000, 1_090_000_001, 2_200_000_000, u32::MAX] {
+        let f = Hertz::new(hz);
+        assert_eq!(f.to_string().parse::<Hertz>().unwrap(), f);
+    }
+    assert_eq!(Hertz::new(978_000_000).to_string(), "978MHz");
+    assert_eq!(SampleRate::new(2_048_000).to_string(), "2.048Msps");
+    assert_eq!(TenthDb::new(496).to_string(), "49.6dB");
+    assert_eq!(TenthDb::new(-5).to_string(), "-0.5dB");
+    for tenths in -100..600 {
+        let g = TenthDb::new(tenths);
+        assert_eq!(g.to_string().parse::<TenthDb>().unwrap(), g);
+    }
+}
+
+#[test]
+fn checked_conversions() {
+    assert_eq!(Hertz::try_from(2_200_000_000u64).unwrap().hz(), 2_200_000_000);
+    assert!(Hertz::try_from(5_000_000_000u64).is_err());
+    assert!(Hertz::try_from(-1i32).is_err());
+    assert!(Hertz::from_mhz(5_000).is_none());
+    assert!(SampleRate::try_from(f64::NAN).is_err());
+}